@@ -0,0 +1,49 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use game_stat::prelude::*;
+
+// compare the sync backends by running this with different features:
+// cargo bench --bench contention --features sync
+// cargo bench --bench contention --features rwlock
+const READS_PER_THREAD: usize = 10_000;
+
+#[cfg(not(feature = "sync"))]
+const BACKEND: &str = "refcell";
+#[cfg(all(feature = "sync", not(feature = "rwlock")))]
+const BACKEND: &str = "mutex";
+#[cfg(all(feature = "sync", feature = "rwlock"))]
+const BACKEND: &str = "rwlock";
+
+fn contention_benchmark(c: &mut Criterion) {
+    let mut stat: Stat<4> = Stat::new(10.0);
+    let _flat = stat.add_modifier(StatModifier::Flat(2.0));
+    let _percent = stat.add_modifier(StatModifier::PercentAdd(0.5));
+
+    c.bench_function(&format!("{BACKEND}: read 1 thread"), |b| {
+        b.iter(|| {
+            for _ in 0..READS_PER_THREAD {
+                black_box(stat.value());
+            }
+        })
+    });
+
+    // a stat can only be shared between threads with the sync feature
+    #[cfg(feature = "sync")]
+    for thread_count in [2, 4, 8] {
+        c.bench_function(&format!("{BACKEND}: read {thread_count} threads"), |b| {
+            b.iter(|| {
+                std::thread::scope(|scope| {
+                    for _ in 0..thread_count {
+                        scope.spawn(|| {
+                            for _ in 0..READS_PER_THREAD {
+                                black_box(stat.value());
+                            }
+                        });
+                    }
+                })
+            })
+        });
+    }
+}
+
+criterion_group!(benches, contention_benchmark);
+criterion_main!(benches);
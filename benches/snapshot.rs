@@ -0,0 +1,39 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use game_stat::prelude::*;
+
+// a typical rollback frame: snapshot every stat, then restore them all
+const STAT_COUNT: usize = 1000;
+
+fn snapshot_benchmark(c: &mut Criterion) {
+    let mut stats: Vec<Stat<4>> = (0..STAT_COUNT).map(|i| Stat::new(i as f32)).collect();
+    let _handles: Vec<StatModifierHandle> = stats
+        .iter_mut()
+        .flat_map(|stat| {
+            [
+                stat.add_modifier(StatModifier::Flat(2.0)),
+                stat.add_modifier(StatModifier::PercentAdd(0.5)),
+                stat.add_modifier(StatModifier::PercentMultiply(1.5)),
+            ]
+        })
+        .collect();
+
+    c.bench_function("snapshot 1000 stats", |b| {
+        b.iter(|| {
+            let snapshots: Vec<_> = stats.iter().map(|stat| stat.snapshot()).collect();
+            black_box(snapshots)
+        })
+    });
+
+    let snapshots: Vec<_> = stats.iter().map(|stat| stat.snapshot()).collect();
+    c.bench_function("restore 1000 stats", |b| {
+        b.iter(|| {
+            for (stat, snapshot) in stats.iter_mut().zip(snapshots.iter()) {
+                stat.restore(snapshot);
+            }
+            black_box(&stats);
+        })
+    });
+}
+
+criterion_group!(benches, snapshot_benchmark);
+criterion_main!(benches);
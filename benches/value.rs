@@ -0,0 +1,31 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use game_stat::prelude::*;
+
+// reading lots of stats every frame, where modifiers rarely change
+const STAT_COUNT: usize = 1000;
+
+fn value_benchmark(c: &mut Criterion) {
+    let mut stats: Vec<Stat<4>> = (0..STAT_COUNT).map(|i| Stat::new(i as f32)).collect();
+    let _handles: Vec<StatModifierHandle> = stats
+        .iter_mut()
+        .flat_map(|stat| {
+            [
+                stat.add_modifier(StatModifier::Flat(2.0)),
+                stat.add_modifier(StatModifier::PercentAdd(0.5)),
+                stat.add_modifier(StatModifier::PercentMultiply(1.5)),
+                stat.add_modifier(StatModifier::Flat(1.0)),
+            ]
+        })
+        .collect();
+
+    c.bench_function("value 1000 stats, nothing changed", |b| {
+        b.iter(|| {
+            for stat in stats.iter() {
+                black_box(stat.value());
+            }
+        })
+    });
+}
+
+criterion_group!(benches, value_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,147 @@
+use crate::cell::{borrow_cell, read_cell};
+use crate::error::StatError;
+use crate::modifier::StatModifier;
+use crate::stat::{Stat, StatInner};
+
+/// How the modifiers with the same order are combined, see [`Stat::set_aggregation()`].
+///
+/// Except for `Sequential`, every modifier is applied to the value the group starts with,
+/// the difference it makes is its effect. `PercentAdd(-0.5)` on 10.0 has an effect of -5.0
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Aggregation {
+    /// One after another, like a stat without aggregation. Only [`StatModifier::Increased`] modifiers are added up
+    #[default]
+    Sequential,
+    /// The effects are added up, `PercentAdd(0.2)` and `PercentAdd(0.3)` give +50%
+    Sum,
+    /// The modifiers are multiplied, `PercentAdd(0.2)` and `PercentAdd(0.3)` give 1.2 * 1.3
+    Product,
+    /// Only the highest effect
+    Max,
+    /// Only the lowest effect
+    Min,
+    /// The strongest increase plus the strongest decrease
+    StrongestBuffAndDebuff,
+    /// All increases added up, plus only the strongest decrease
+    SumBuffsStrongestDebuff,
+}
+
+impl Aggregation {
+    pub(crate) fn apply<'a>(
+        &self,
+        modifiers: impl Iterator<Item = &'a StatModifier> + Clone,
+        value: &mut f32,
+    ) {
+        let input = *value;
+        let effect = |modifier: &StatModifier| {
+            let mut value = input;
+            modifier.apply(&mut value);
+            value - input
+        };
+        match self {
+            Aggregation::Sequential => {
+                let increased: f32 = modifiers
+                    .clone()
+                    .filter_map(|modifier| match modifier {
+                        StatModifier::Increased(v) => Some(*v),
+                        _ => None,
+                    })
+                    .sum();
+                // all increased modifiers are applied at once, where the first one is
+                let mut increased_applied = false;
+                for modifier in modifiers {
+                    match modifier {
+                        StatModifier::Increased(_) if increased_applied => {}
+                        StatModifier::Increased(_) => {
+                            StatModifier::Increased(increased).apply(value);
+                            increased_applied = true;
+                        }
+                        _ => modifier.apply(value),
+                    }
+                }
+            }
+            Aggregation::Sum => *value += modifiers.map(effect).sum::<f32>(),
+            // nothing to multiply when the group starts at 0
+            Aggregation::Product if input == 0.0 => *value += modifiers.map(effect).sum::<f32>(),
+            Aggregation::Product => {
+                *value = modifiers
+                    .map(effect)
+                    .fold(input, |value, effect| value * (1.0 + effect / input))
+            }
+            Aggregation::Max => *value += modifiers.map(effect).reduce(f32::max).unwrap_or(0.0),
+            Aggregation::Min => *value += modifiers.map(effect).reduce(f32::min).unwrap_or(0.0),
+            Aggregation::StrongestBuffAndDebuff => {
+                let (buff, debuff) = modifiers
+                    .map(effect)
+                    .fold((0.0f32, 0.0f32), |(buff, debuff), effect| {
+                        (buff.max(effect), debuff.min(effect))
+                    });
+                *value += buff + debuff;
+            }
+            Aggregation::SumBuffsStrongestDebuff => {
+                let (buffs, debuff) = modifiers
+                    .map(effect)
+                    .fold((0.0, 0.0f32), |(buffs, debuff), effect| {
+                        (buffs + effect.max(0.0), debuff.min(effect))
+                    });
+                *value += buffs + debuff;
+            }
+        }
+    }
+}
+
+impl<const M: usize> Stat<M> {
+    /// Decides how the modifiers with this order are combined, the default is [`Aggregation::Sequential`].
+    /// panics if refcell is borrowed, see [`Stat::try_set_aggregation()`]
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// let mut speed: Stat<4> = Stat::new(10.0);
+    /// // speed boosts add up, but only the strongest slow applies
+    /// let percent_order = StatModifier::PercentAdd(0.0).default_order();
+    /// speed.set_aggregation(percent_order, Aggregation::SumBuffsStrongestDebuff);
+    /// let _boots = speed.add_modifier(StatModifier::PercentAdd(0.25));
+    /// let _haste = speed.add_modifier(StatModifier::PercentAdd(0.5));
+    /// let _frost = speed.add_modifier(StatModifier::PercentAdd(-0.5));
+    /// let _mud = speed.add_modifier(StatModifier::PercentAdd(-0.25));
+    /// assert_eq!(speed.value(), 12.5);
+    /// ```
+    pub fn set_aggregation(&mut self, order: i32, aggregation: Aggregation) {
+        self.try_set_aggregation(order, aggregation).unwrap()
+    }
+
+    /// Same as [`Stat::set_aggregation()`], but returns an error instead of panicking
+    pub fn try_set_aggregation(
+        &mut self,
+        order: i32,
+        aggregation: Aggregation,
+    ) -> Result<(), StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        inner.update_modifiers();
+        match inner.aggregations.binary_search_by_key(&order, |(o, _)| *o) {
+            Ok(index) if aggregation == Aggregation::Sequential => {
+                inner.aggregations.remove(index);
+            }
+            Ok(index) => inner.aggregations[index].1 = aggregation,
+            Err(_) if aggregation == Aggregation::Sequential => {}
+            Err(index) => inner.aggregations.insert(index, (order, aggregation)),
+        }
+        inner.calculate_internal_value();
+        Ok(())
+    }
+
+    /// How the modifiers with this order are combined
+    /// panics if refcell is borrowed
+    pub fn aggregation(&self, order: i32) -> Aggregation {
+        read_cell(&self.inner).unwrap().aggregation(order)
+    }
+}
+
+impl<const M: usize> StatInner<M> {
+    pub(crate) fn aggregation(&self, order: i32) -> Aggregation {
+        self.aggregations
+            .binary_search_by_key(&order, |(o, _)| *o)
+            .map_or(Aggregation::Sequential, |index| self.aggregations[index].1)
+    }
+}
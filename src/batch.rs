@@ -0,0 +1,126 @@
+use crate::cell::{borrow_cell, RefMut};
+use crate::error::StatError;
+use crate::modifier::StatModifier;
+use crate::stat::{ModifierKey, Stat, StatInner, StatModifierHandle};
+
+/// Collects changes to a [`Stat`], created by [`Stat::batch()`].
+///
+/// The modifiers are sorted and the value is calculated once when the batch is done, instead of for every change.
+/// The stat stays borrowed (locked with the sync feature) during the whole batch, so nobody sees it half way through.
+pub struct StatBatch<'a, const M: usize> {
+    inner: RefMut<'a, StatInner<M>>,
+}
+
+impl<const M: usize> Stat<M> {
+    /// Make several changes to the stat, with a single recalculation at the end
+    /// panics if refcell is borrowed, see [`Stat::try_batch()`]
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// let mut stat: Stat<4> = Stat::new(10.0);
+    /// let armor_set = stat.batch(|batch| {
+    ///     [
+    ///         batch.add_modifier(StatModifier::Flat(5.0)),
+    ///         batch.add_modifier(StatModifier::Flat(5.0)),
+    ///         batch.add_modifier(StatModifier::PercentMultiply(2.0)),
+    ///     ]
+    /// });
+    /// assert_eq!(stat.value(), 40.0);
+    /// drop(armor_set);
+    /// assert_eq!(stat.value(), 10.0);
+    /// ```
+    pub fn batch<R>(&mut self, f: impl FnOnce(&mut StatBatch<'_, M>) -> R) -> R {
+        self.try_batch(f).unwrap()
+    }
+
+    /// Same as [`Stat::batch()`], but returns an error instead of panicking
+    pub fn try_batch<R>(
+        &mut self,
+        f: impl FnOnce(&mut StatBatch<'_, M>) -> R,
+    ) -> Result<R, StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        inner.update_modifiers();
+        let mut batch = StatBatch { inner };
+        let result = f(&mut batch);
+        batch.inner.calculate_internal_value();
+        Ok(result)
+    }
+}
+
+impl<const M: usize> StatBatch<'_, M> {
+    /// Same as [`Stat::set_base_value()`]
+    pub fn set_base_value(&mut self, base_value: f32) {
+        self.inner.base_value = base_value;
+    }
+
+    /// Same as [`Stat::add_modifier()`]
+    /// panics if the capacity is exceeded in strict mode, see [`StatBatch::try_add_modifier()`]
+    pub fn add_modifier(&mut self, modifier: StatModifier) -> StatModifierHandle {
+        self.try_add_modifier(modifier).unwrap()
+    }
+
+    /// Same as [`StatBatch::add_modifier()`], but returns an error instead of panicking
+    pub fn try_add_modifier(
+        &mut self,
+        modifier: StatModifier,
+    ) -> Result<StatModifierHandle, StatError> {
+        self.try_add_modifier_with_order(modifier, modifier.default_order())
+    }
+
+    /// Same as [`Stat::add_modifier_with_order()`]
+    /// panics if the capacity is exceeded in strict mode, see [`StatBatch::try_add_modifier_with_order()`]
+    pub fn add_modifier_with_order(
+        &mut self,
+        modifier: StatModifier,
+        order: i32,
+    ) -> StatModifierHandle {
+        self.try_add_modifier_with_order(modifier, order).unwrap()
+    }
+
+    /// Same as [`StatBatch::add_modifier_with_order()`], but returns an error instead of panicking
+    pub fn try_add_modifier_with_order(
+        &mut self,
+        modifier: StatModifier,
+        order: i32,
+    ) -> Result<StatModifierHandle, StatError> {
+        self.inner.push_handle_modifier(modifier, order)
+    }
+
+    /// Same as [`Stat::insert_modifier()`]
+    /// panics if the capacity is exceeded in strict mode, see [`StatBatch::try_insert_modifier()`]
+    pub fn insert_modifier(&mut self, modifier: StatModifier) -> ModifierKey {
+        self.try_insert_modifier(modifier).unwrap()
+    }
+
+    /// Same as [`StatBatch::insert_modifier()`], but returns an error instead of panicking
+    pub fn try_insert_modifier(
+        &mut self,
+        modifier: StatModifier,
+    ) -> Result<ModifierKey, StatError> {
+        self.try_insert_modifier_with_order(modifier, modifier.default_order())
+    }
+
+    /// Same as [`Stat::insert_modifier_with_order()`]
+    /// panics if the capacity is exceeded in strict mode, see [`StatBatch::try_insert_modifier_with_order()`]
+    pub fn insert_modifier_with_order(
+        &mut self,
+        modifier: StatModifier,
+        order: i32,
+    ) -> ModifierKey {
+        self.try_insert_modifier_with_order(modifier, order)
+            .unwrap()
+    }
+
+    /// Same as [`StatBatch::insert_modifier_with_order()`], but returns an error instead of panicking
+    pub fn try_insert_modifier_with_order(
+        &mut self,
+        modifier: StatModifier,
+        order: i32,
+    ) -> Result<ModifierKey, StatError> {
+        self.inner.push_keyed_modifier(modifier, order)
+    }
+
+    /// Same as [`Stat::remove_modifier()`]
+    pub fn remove_modifier(&mut self, key: ModifierKey) -> bool {
+        self.inner.remove_modifier(key)
+    }
+}
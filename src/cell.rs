@@ -0,0 +1,151 @@
+use crate::error::StatError;
+
+// By default (single-threaded) implementation is most optimized by using rc
+// if one needs Stat to live in a multithreaded environment, enabling sync feature uses Arc + Mutex instead
+// the lock comes from std, or from the spin crate with the spin feature (for no_std targets)
+// the rwlock feature swaps the Mutex for a RwLock, so reading a stat from many threads doesn't serialize
+#[cfg(not(feature = "sync"))]
+pub(crate) type ReferenceCounted<T> = alloc::rc::Rc<T>;
+#[cfg(not(feature = "sync"))]
+pub(crate) type Weak<T> = alloc::rc::Weak<T>;
+#[cfg(not(feature = "sync"))]
+pub(crate) type InteriorCell<T> = core::cell::RefCell<T>;
+#[cfg(feature = "sync")]
+pub(crate) type ReferenceCounted<T> = alloc::sync::Arc<T>;
+#[cfg(feature = "sync")]
+pub(crate) type Weak<T> = alloc::sync::Weak<T>;
+#[cfg(all(feature = "sync", not(feature = "spin"), not(feature = "rwlock")))]
+pub(crate) type InteriorCell<T> = std::sync::Mutex<T>;
+#[cfg(all(feature = "sync", not(feature = "spin"), feature = "rwlock"))]
+pub(crate) type InteriorCell<T> = std::sync::RwLock<T>;
+#[cfg(all(feature = "spin", not(feature = "rwlock")))]
+pub(crate) type InteriorCell<T> = spin::Mutex<T>;
+#[cfg(all(feature = "spin", feature = "rwlock"))]
+pub(crate) type InteriorCell<T> = spin::RwLock<T>;
+
+// InteriorCell is only the cell, sharing is always spelled out as ReferenceCounted<InteriorCell<T>>
+// this way Clone behaves the same with and without the sync feature
+#[inline]
+pub(crate) fn new_interior_cell<T>(value: T) -> InteriorCell<T> {
+    InteriorCell::new(value)
+}
+
+#[cfg(not(feature = "sync"))]
+pub(crate) type RefMut<'a, T> = core::cell::RefMut<'a, T>;
+#[cfg(not(feature = "sync"))]
+pub(crate) type Ref<'a, T> = core::cell::Ref<'a, T>;
+#[cfg(all(feature = "sync", not(feature = "spin"), not(feature = "rwlock")))]
+pub(crate) type RefMut<'a, T> = std::sync::MutexGuard<'a, T>;
+#[cfg(all(feature = "sync", not(feature = "spin"), not(feature = "rwlock")))]
+pub(crate) type Ref<'a, T> = std::sync::MutexGuard<'a, T>;
+#[cfg(all(feature = "sync", not(feature = "spin"), feature = "rwlock"))]
+pub(crate) type RefMut<'a, T> = std::sync::RwLockWriteGuard<'a, T>;
+#[cfg(all(feature = "sync", not(feature = "spin"), feature = "rwlock"))]
+pub(crate) type Ref<'a, T> = std::sync::RwLockReadGuard<'a, T>;
+#[cfg(all(feature = "spin", not(feature = "rwlock")))]
+pub(crate) type RefMut<'a, T> = spin::MutexGuard<'a, T>;
+#[cfg(all(feature = "spin", not(feature = "rwlock")))]
+pub(crate) type Ref<'a, T> = spin::MutexGuard<'a, T>;
+#[cfg(all(feature = "spin", feature = "rwlock"))]
+pub(crate) type RefMut<'a, T> = spin::RwLockWriteGuard<'a, T>;
+#[cfg(all(feature = "spin", feature = "rwlock"))]
+pub(crate) type Ref<'a, T> = spin::RwLockReadGuard<'a, T>;
+
+#[cfg(not(feature = "sync"))]
+#[inline]
+pub(crate) fn borrow_cell<T>(cell: &InteriorCell<T>) -> Result<RefMut<'_, T>, StatError> {
+    cell.try_borrow_mut().map_err(|_| StatError::Borrowed)
+}
+
+#[cfg(not(feature = "sync"))]
+#[inline]
+pub(crate) fn read_cell<T>(cell: &InteriorCell<T>) -> Result<Ref<'_, T>, StatError> {
+    cell.try_borrow().map_err(|_| StatError::Borrowed)
+}
+
+// a locked mutex blocks until it's free (another thread is using it), only poisoning is an error
+// with a mutex, reading is the same as writing
+#[cfg(all(feature = "sync", not(feature = "spin"), not(feature = "rwlock")))]
+#[inline]
+pub(crate) fn borrow_cell<T>(cell: &InteriorCell<T>) -> Result<RefMut<'_, T>, StatError> {
+    cell.lock().map_err(|_| StatError::Poisoned)
+}
+
+#[cfg(all(feature = "sync", not(feature = "spin"), not(feature = "rwlock")))]
+#[inline]
+pub(crate) fn read_cell<T>(cell: &InteriorCell<T>) -> Result<Ref<'_, T>, StatError> {
+    borrow_cell(cell)
+}
+
+#[cfg(all(feature = "sync", not(feature = "spin"), feature = "rwlock"))]
+#[inline]
+pub(crate) fn borrow_cell<T>(cell: &InteriorCell<T>) -> Result<RefMut<'_, T>, StatError> {
+    cell.write().map_err(|_| StatError::Poisoned)
+}
+
+#[cfg(all(feature = "sync", not(feature = "spin"), feature = "rwlock"))]
+#[inline]
+pub(crate) fn read_cell<T>(cell: &InteriorCell<T>) -> Result<Ref<'_, T>, StatError> {
+    cell.read().map_err(|_| StatError::Poisoned)
+}
+
+// a spin lock can't be poisoned
+#[cfg(all(feature = "spin", not(feature = "rwlock")))]
+#[inline]
+pub(crate) fn borrow_cell<T>(cell: &InteriorCell<T>) -> Result<RefMut<'_, T>, StatError> {
+    Ok(cell.lock())
+}
+
+#[cfg(all(feature = "spin", not(feature = "rwlock")))]
+#[inline]
+pub(crate) fn read_cell<T>(cell: &InteriorCell<T>) -> Result<Ref<'_, T>, StatError> {
+    Ok(cell.lock())
+}
+
+#[cfg(all(feature = "spin", feature = "rwlock"))]
+#[inline]
+pub(crate) fn borrow_cell<T>(cell: &InteriorCell<T>) -> Result<RefMut<'_, T>, StatError> {
+    Ok(cell.write())
+}
+
+#[cfg(all(feature = "spin", feature = "rwlock"))]
+#[inline]
+pub(crate) fn read_cell<T>(cell: &InteriorCell<T>) -> Result<Ref<'_, T>, StatError> {
+    Ok(cell.read())
+}
+
+// Counts how many modifier handles have been dropped, shared by a stat and all handles it created.
+// A stat remembers the last count it has seen, if the count changed some modifier must be removed.
+#[cfg(not(feature = "sync"))]
+#[derive(Debug, Default)]
+pub(crate) struct DropCounter(core::cell::Cell<u32>);
+
+#[cfg(not(feature = "sync"))]
+impl DropCounter {
+    #[inline]
+    pub(crate) fn increment(&self) {
+        self.0.set(self.0.get().wrapping_add(1));
+    }
+
+    #[inline]
+    pub(crate) fn get(&self) -> u32 {
+        self.0.get()
+    }
+}
+
+#[cfg(feature = "sync")]
+#[derive(Debug, Default)]
+pub(crate) struct DropCounter(core::sync::atomic::AtomicU32);
+
+#[cfg(feature = "sync")]
+impl DropCounter {
+    #[inline]
+    pub(crate) fn increment(&self) {
+        self.0.fetch_add(1, core::sync::atomic::Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn get(&self) -> u32 {
+        self.0.load(core::sync::atomic::Ordering::Acquire)
+    }
+}
@@ -0,0 +1,208 @@
+use crate::derived::{DerivedStat, StatSource};
+use crate::error::StatError;
+use crate::modifier::StatModifier;
+use crate::stat::{Stat, StatModifierHandle};
+use alloc::vec::Vec;
+
+/// How a [`Curve`] gets from one keyframe to the next
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Interpolation {
+    /// Keeps the value of the previous keyframe until the next one
+    Step,
+    /// Straight lines between keyframes
+    #[default]
+    Linear,
+    /// A smooth spline (Catmull-Rom) that goes through every keyframe
+    CatmullRom,
+}
+
+/// Keyframes `(input, output)` plus an [`Interpolation`], for level scaling, damage falloff or ramping buffs.
+/// Before the first and after the last keyframe the curve stays flat
+/// ```
+/// # use game_stat::{Curve, Interpolation};
+/// let falloff = Curve::new([(0.0, 1.0), (10.0, 1.0), (30.0, 0.25)], Interpolation::Linear).unwrap();
+/// assert_eq!(falloff.sample(5.0), 1.0);
+/// assert_eq!(falloff.sample(20.0), 0.625);
+/// assert_eq!(falloff.sample(100.0), 0.25);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "CurveData")
+)]
+pub struct Curve {
+    // sorted by input
+    keyframes: Vec<(f32, f32)>,
+    interpolation: Interpolation,
+}
+
+// checked by Curve::new() when deserialized
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CurveData {
+    keyframes: Vec<(f32, f32)>,
+    #[serde(default)]
+    interpolation: Interpolation,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<CurveData> for Curve {
+    type Error = CurveError;
+
+    fn try_from(data: CurveData) -> Result<Self, Self::Error> {
+        Self::new(data.keyframes, data.interpolation)
+    }
+}
+
+/// Returned by [`Curve::new()`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum CurveError {
+    /// A curve needs at least one keyframe
+    Empty,
+    /// Keyframes have to be finite numbers
+    NotFinite,
+    /// Two keyframes have this input
+    DuplicateInput(f32),
+}
+
+impl core::fmt::Display for CurveError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CurveError::Empty => write!(f, "curve has no keyframes"),
+            CurveError::NotFinite => write!(f, "curve keyframes have to be finite numbers"),
+            CurveError::DuplicateInput(input) => {
+                write!(f, "curve has two keyframes at {input}")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CurveError {}
+
+impl Curve {
+    /// The keyframes don't have to be sorted
+    pub fn new(
+        keyframes: impl IntoIterator<Item = (f32, f32)>,
+        interpolation: Interpolation,
+    ) -> Result<Self, CurveError> {
+        let mut keyframes: Vec<(f32, f32)> = keyframes.into_iter().collect();
+        if keyframes.is_empty() {
+            return Err(CurveError::Empty);
+        }
+        if keyframes
+            .iter()
+            .any(|(input, output)| !input.is_finite() || !output.is_finite())
+        {
+            return Err(CurveError::NotFinite);
+        }
+        keyframes.sort_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some(pair) = keyframes.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(CurveError::DuplicateInput(pair[0].0));
+        }
+        Ok(Self {
+            keyframes,
+            interpolation,
+        })
+    }
+
+    pub fn keyframes(&self) -> &[(f32, f32)] {
+        &self.keyframes
+    }
+
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    /// The output of the curve at `input`
+    pub fn sample(&self, input: f32) -> f32 {
+        let keyframes = &self.keyframes;
+        // the first keyframe after input
+        let next = keyframes.partition_point(|(x, _)| *x <= input);
+        if next == 0 {
+            return keyframes[0].1;
+        }
+        if next == keyframes.len() {
+            return keyframes[next - 1].1;
+        }
+        let (x0, y0) = keyframes[next - 1];
+        let (x1, y1) = keyframes[next];
+        let width = x1 - x0;
+        let t = (input - x0) / width;
+        match self.interpolation {
+            Interpolation::Step => y0,
+            Interpolation::Linear => y0 + (y1 - y0) * t,
+            Interpolation::CatmullRom => {
+                let m0 = self.tangent(next - 1) * width;
+                let m1 = self.tangent(next) * width;
+                let t2 = t * t;
+                let t3 = t2 * t;
+                (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+                    + (t3 - 2.0 * t2 + t) * m0
+                    + (-2.0 * t3 + 3.0 * t2) * y1
+                    + (t3 - t2) * m1
+            }
+        }
+    }
+
+    // the slope through the neighbours of a keyframe, one sided at the ends
+    fn tangent(&self, index: usize) -> f32 {
+        let before = self.keyframes[index.saturating_sub(1)];
+        let after = self.keyframes[(index + 1).min(self.keyframes.len() - 1)];
+        (after.1 - before.1) / (after.0 - before.0)
+    }
+}
+
+impl<const M: usize> Stat<M> {
+    /// Multiplies the stat by `curve(source value)`, and follows the source when it changes.
+    /// Use [`Stat::add_scaled_modifier()`] with [`Curve::sample()`] for other kinds of modifiers.
+    /// panics if refcell is borrowed, the capacity is exceeded in strict mode,
+    /// or `source` depends on this stat, see [`Stat::try_add_curve_modifier()`]
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// # use game_stat::{Curve, Interpolation};
+    /// let mut level: Stat<2> = Stat::new(1.0);
+    /// let mut damage: Stat<2> = Stat::new(10.0);
+    /// let scaling = Curve::new([(1.0, 1.0), (10.0, 2.0)], Interpolation::Linear).unwrap();
+    /// let _handle = damage.add_curve_modifier(&level.source(), scaling);
+    /// assert_eq!(damage.value(), 10.0);
+    /// level.set_base_value(10.0);
+    /// assert_eq!(damage.value(), 20.0);
+    /// ```
+    pub fn add_curve_modifier(&mut self, source: &StatSource, curve: Curve) -> StatModifierHandle {
+        self.try_add_curve_modifier(source, curve).unwrap()
+    }
+
+    /// Same as [`Stat::add_curve_modifier()`], but returns an error instead of panicking
+    pub fn try_add_curve_modifier(
+        &mut self,
+        source: &StatSource,
+        curve: Curve,
+    ) -> Result<StatModifierHandle, StatError> {
+        self.try_add_scaled_modifier(source, move |value| {
+            StatModifier::PercentMultiply(curve.sample(value))
+        })
+    }
+}
+
+impl<const M: usize> DerivedStat<M> {
+    /// Creates a stat whose base value is `curve(source value)`, like health growing with the level
+    /// panics if the source is borrowed
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// # use game_stat::{Curve, Interpolation};
+    /// let mut level: Stat<2> = Stat::new(1.0);
+    /// let growth = Curve::new([(1.0, 100.0), (50.0, 1000.0)], Interpolation::CatmullRom).unwrap();
+    /// let max_health: DerivedStat<2> = DerivedStat::from_curve(level.source(), growth);
+    /// assert_eq!(max_health.value(), 100.0);
+    /// level.set_base_value(50.0);
+    /// assert_eq!(max_health.value(), 1000.0);
+    /// ```
+    pub fn from_curve(source: StatSource, curve: Curve) -> Self {
+        Self::new([source], move |values: &[f32]| curve.sample(values[0]))
+    }
+}
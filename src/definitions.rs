@@ -0,0 +1,394 @@
+use crate::cell::borrow_cell;
+use crate::error::StatError;
+use crate::modifier::StatModifier;
+use crate::reload::TemplateInstance;
+use crate::sheet::StatSheet;
+use crate::stat::StatModifierHandle;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+
+/// A modifier written by a designer in a data file, see [`ModifierDefinitions`]
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModifierTemplate {
+    /// The key of the stat in the [`StatSheet`] the modifier is added to
+    #[serde(deserialize_with = "non_empty")]
+    pub stat: String,
+    #[serde(deserialize_with = "finite_modifier")]
+    pub modifier: StatModifier,
+    /// Uses [`StatModifier::default_order()`] if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<i32>,
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "tags"
+    )]
+    pub tags: Vec<String>,
+    /// In seconds, the modifier runs out after this much [`super::Stat::tick()`] (or [`StatSheet::tick()`]).
+    /// None means it stays until the handle is dropped
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "duration"
+    )]
+    pub duration: Option<f32>,
+}
+
+// every field is checked while it's deserialized, that way the error points at the field in the file
+
+fn non_empty<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let value = <String as serde::Deserialize>::deserialize(deserializer)?;
+    if value.is_empty() {
+        return Err(serde::de::Error::custom("`stat` can't be empty"));
+    }
+    Ok(value)
+}
+
+fn finite_modifier<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<StatModifier, D::Error> {
+    let modifier = <StatModifier as serde::Deserialize>::deserialize(deserializer)?;
+    let values = match modifier {
+        StatModifier::Flat(value)
+        | StatModifier::PercentAdd(value)
+        | StatModifier::PercentMultiply(value)
+        | StatModifier::HardCap(value)
+        | StatModifier::Increased(value)
+        | StatModifier::More(value) => [value, value],
+        StatModifier::SoftCap { threshold, rate } => [threshold, rate],
+        StatModifier::DiminishingReturns { max, k } => [max, k],
+    };
+    if let Some(value) = values.into_iter().find(|value| !value.is_finite()) {
+        return Err(serde::de::Error::custom(alloc::format!(
+            "modifier value {value} is not a finite number"
+        )));
+    }
+    match modifier {
+        StatModifier::SoftCap { rate, .. } if rate < 0.0 => Err(serde::de::Error::custom(
+            alloc::format!("soft cap rate {rate} can't be negative"),
+        )),
+        StatModifier::DiminishingReturns { k, .. } if k <= 0.0 => Err(serde::de::Error::custom(
+            alloc::format!("diminishing returns k {k} has to be more than 0"),
+        )),
+        _ => Ok(modifier),
+    }
+}
+
+fn tags<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let tags = <Vec<String> as serde::Deserialize>::deserialize(deserializer)?;
+    if tags.iter().any(String::is_empty) {
+        return Err(serde::de::Error::custom("tags can't be empty"));
+    }
+    Ok(tags)
+}
+
+fn duration<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<f32>, D::Error> {
+    let duration = <Option<f32> as serde::Deserialize>::deserialize(deserializer)?;
+    if let Some(duration) = duration.filter(|d| !(d.is_finite() && *d > 0.0)) {
+        return Err(serde::de::Error::custom(alloc::format!(
+            "duration {duration} has to be more than 0"
+        )));
+    }
+    Ok(duration)
+}
+
+impl ModifierTemplate {
+    /// The order the modifier is added with
+    pub fn order(&self) -> i32 {
+        self.order.unwrap_or_else(|| self.modifier.default_order())
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+}
+
+/// Named [`ModifierTemplate`]s loaded from a data file (RON, JSON or TOML with the `ron`, `json` and `toml` features).
+///
+/// Every template is checked while loading, the error tells the file and line of the problem.
+/// ```
+/// # #[cfg(feature = "json")] {
+/// # use game_stat::prelude::*;
+/// # use game_stat::ModifierDefinitions;
+/// let mut definitions = ModifierDefinitions::from_json_str(r#"{
+///     "fire_sword_bonus": { "stat": "attack", "modifier": { "Flat": 5.0 }, "tags": ["fire"] },
+///     "rage": { "stat": "attack", "modifier": { "PercentMultiply": 2.0 }, "duration": 10.0 }
+/// }"#).unwrap();
+///
+/// let mut sheet: StatSheet<&str, 2> = StatSheet::new();
+/// sheet.insert("attack", Stat::new(10.0));
+/// let _handle = definitions.instantiate("fire_sword_bonus", &mut sheet).unwrap();
+/// assert_eq!(sheet.value("attack"), Some(15.0));
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, serde::Serialize)]
+#[serde(transparent)]
+pub struct ModifierDefinitions {
+    pub(crate) templates: BTreeMap<String, ModifierTemplate>,
+    // the modifiers created from the templates, so a reload can update them
+    #[serde(skip)]
+    pub(crate) instances: Vec<TemplateInstance>,
+}
+
+/// Only the templates are compared
+impl PartialEq for ModifierDefinitions {
+    fn eq(&self, other: &Self) -> bool {
+        self.templates == other.templates
+    }
+}
+
+// a map, but a name used twice is an error instead of the last one winning
+impl<'de> serde::Deserialize<'de> for ModifierDefinitions {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct DefinitionsVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for DefinitionsVisitor {
+            type Value = ModifierDefinitions;
+
+            fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                f.write_str("a map of modifier templates by name")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut templates = BTreeMap::new();
+                while let Some(name) = map.next_key::<String>()? {
+                    if templates.contains_key(&name) {
+                        return Err(serde::de::Error::custom(alloc::format!(
+                            "template `{name}` is defined twice"
+                        )));
+                    }
+                    let template = map.next_value()?;
+                    templates.insert(name, template);
+                }
+                Ok(ModifierDefinitions {
+                    templates,
+                    instances: Vec::new(),
+                })
+            }
+        }
+
+        deserializer.deserialize_map(DefinitionsVisitor)
+    }
+}
+
+/// Returned when loading [`ModifierDefinitions`] fails
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DefinitionError {
+    /// Only set when loading from a file
+    pub file: Option<String>,
+    /// Starts at 1, None if the error isn't about a specific place (the file couldn't be read)
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl core::fmt::Display for DefinitionError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{file}:")?;
+        }
+        if let Some(line) = self.line {
+            write!(f, "{line}:")?;
+        }
+        if let Some(column) = self.column {
+            write!(f, "{column}:")?;
+        }
+        if self.file.is_some() || self.line.is_some() {
+            f.write_str(" ")?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DefinitionError {}
+
+/// Returned by [`ModifierDefinitions::instantiate()`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TemplateError {
+    /// There's no template with this name
+    UnknownTemplate(String),
+    /// The sheet doesn't have the stat the template modifies
+    UnknownStat(String),
+    Stat(StatError),
+}
+
+impl core::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TemplateError::UnknownTemplate(name) => write!(f, "unknown modifier template `{name}`"),
+            TemplateError::UnknownStat(stat) => write!(f, "unknown stat `{stat}`"),
+            TemplateError::Stat(error) => error.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TemplateError {}
+
+impl From<StatError> for TemplateError {
+    fn from(error: StatError) -> Self {
+        TemplateError::Stat(error)
+    }
+}
+
+impl ModifierDefinitions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a template, returns the old template if there already was one with this name
+    pub fn insert(
+        &mut self,
+        name: impl Into<String>,
+        template: ModifierTemplate,
+    ) -> Option<ModifierTemplate> {
+        self.templates.insert(name.into(), template)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ModifierTemplate> {
+        self.templates.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ModifierTemplate)> {
+        self.templates
+            .iter()
+            .map(|(name, template)| (name.as_str(), template))
+    }
+
+    /// All templates with this tag
+    pub fn tagged<'a>(
+        &'a self,
+        tag: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a ModifierTemplate)> + 'a {
+        self.iter()
+            .filter(move |(_, template)| template.has_tag(tag))
+    }
+
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+
+    /// Adds the modifier of the template to its stat in `sheet`, the modifier lives as long as the handle
+    /// (or until its duration runs out).
+    /// The modifier is updated when the template changes, see [`ModifierDefinitions::reload()`]
+    pub fn instantiate<K: Ord + Borrow<str>, const M: usize>(
+        &mut self,
+        name: &str,
+        sheet: &mut StatSheet<K, M>,
+    ) -> Result<StatModifierHandle, TemplateError> {
+        let template = self
+            .get(name)
+            .ok_or_else(|| TemplateError::UnknownTemplate(name.to_string()))?;
+        let stat = sheet
+            .get_mut(template.stat.as_str())
+            .ok_or_else(|| TemplateError::UnknownStat(template.stat.clone()))?;
+        let handle = borrow_cell(&stat.inner)?.push_timed_handle_modifier(
+            template.modifier,
+            template.order(),
+            template.duration,
+        )?;
+        let stat_key = template.stat.clone();
+        self.track_instance(name, &stat_key, stat, &handle);
+        Ok(handle)
+    }
+
+    /// Parses RON, like `{ "fire_sword_bonus": (stat: "attack", modifier: Flat(5.0), tags: ["fire"]) }`
+    #[cfg(feature = "ron")]
+    pub fn from_ron_str(source: &str) -> Result<Self, DefinitionError> {
+        ron::from_str(source).map_err(|error| DefinitionError {
+            file: None,
+            line: Some(error.position.line),
+            column: Some(error.position.col),
+            message: error.code.to_string(),
+        })
+    }
+
+    /// Parses JSON, like `{ "fire_sword_bonus": { "stat": "attack", "modifier": { "Flat": 5.0 }, "tags": ["fire"] } }`
+    #[cfg(feature = "json")]
+    pub fn from_json_str(source: &str) -> Result<Self, DefinitionError> {
+        serde_json::from_str(source).map_err(|error| {
+            // the position is part of the message
+            let message = error.to_string();
+            let position = alloc::format!(" at line {} column {}", error.line(), error.column());
+            DefinitionError {
+                file: None,
+                line: Some(error.line()),
+                column: Some(error.column()),
+                message: message
+                    .strip_suffix(position.as_str())
+                    .unwrap_or(&message)
+                    .to_string(),
+            }
+        })
+    }
+
+    /// Parses TOML, every template is a table
+    /// ```toml
+    /// [fire_sword_bonus]
+    /// stat = "attack"
+    /// modifier = { Flat = 5.0 }
+    /// tags = ["fire"]
+    /// ```
+    #[cfg(feature = "toml")]
+    pub fn from_toml_str(source: &str) -> Result<Self, DefinitionError> {
+        toml::from_str(source).map_err(|error| {
+            // toml tells the byte offset
+            let (line, column) = match error.span() {
+                Some(span) => {
+                    let before = &source[..span.start];
+                    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+                    (
+                        Some(before.matches('\n').count() + 1),
+                        Some(before[line_start..].chars().count() + 1),
+                    )
+                }
+                None => (None, None),
+            };
+            DefinitionError {
+                file: None,
+                line,
+                column,
+                message: error.message().to_string(),
+            }
+        })
+    }
+
+    /// Loads a file, the format is picked from the extension (`.ron`, `.json` or `.toml`)
+    #[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, DefinitionError> {
+        let path = path.as_ref();
+        let file = path.display().to_string();
+        let error = |message: String| DefinitionError {
+            file: Some(file.clone()),
+            line: None,
+            column: None,
+            message,
+        };
+        let source = std::fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        let result = match path.extension().and_then(|e| e.to_str()) {
+            #[cfg(feature = "ron")]
+            Some("ron") => Self::from_ron_str(&source),
+            #[cfg(feature = "json")]
+            Some("json") => Self::from_json_str(&source),
+            #[cfg(feature = "toml")]
+            Some("toml") => Self::from_toml_str(&source),
+            _ => return Err(error("unsupported file extension".to_string())),
+        };
+        result.map_err(|e| DefinitionError {
+            file: Some(file.clone()),
+            ..e
+        })
+    }
+}
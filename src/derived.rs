@@ -0,0 +1,363 @@
+use crate::cell::{borrow_cell, read_cell, InteriorCell, ReferenceCounted, Weak};
+use crate::error::StatError;
+use crate::modifier::StatModifier;
+use crate::scaled::ModifierScale;
+use crate::stat::{ModifierKey, Stat, StatInner, StatModifierHandle};
+use alloc::vec::Vec;
+use core::ops::Deref;
+
+/// The formula of a [`DerivedStat`], gets the values of the inputs in the same order they were given.
+/// Any `Fn(&[f32]) -> f32` works (with the sync feature it also has to be `Send + Sync`)
+#[cfg(not(feature = "sync"))]
+pub trait StatFormula: Fn(&[f32]) -> f32 + 'static {}
+#[cfg(not(feature = "sync"))]
+impl<F: Fn(&[f32]) -> f32 + 'static> StatFormula for F {}
+
+/// The formula of a [`DerivedStat`], gets the values of the inputs in the same order they were given.
+/// Any `Fn(&[f32]) -> f32` works (with the sync feature it also has to be `Send + Sync`)
+#[cfg(feature = "sync")]
+pub trait StatFormula: Fn(&[f32]) -> f32 + Send + Sync + 'static {}
+#[cfg(feature = "sync")]
+impl<F: Fn(&[f32]) -> f32 + Send + Sync + 'static> StatFormula for F {}
+
+// lets a stat read other stats without knowing their M
+pub(crate) trait ValueSource {
+    fn value_and_version(&self) -> Result<(f32, u64), StatError>;
+    // true if target is this stat, or one of the stats it reads from
+    fn depends_on(&self, target: *const ()) -> Result<bool, StatError>;
+}
+
+#[cfg(not(feature = "sync"))]
+pub(crate) type SourceCell = ReferenceCounted<dyn ValueSource>;
+#[cfg(not(feature = "sync"))]
+pub(crate) type WeakSourceCell = Weak<dyn ValueSource>;
+#[cfg(feature = "sync")]
+pub(crate) type SourceCell = ReferenceCounted<dyn ValueSource + Send + Sync>;
+#[cfg(feature = "sync")]
+pub(crate) type WeakSourceCell = Weak<dyn ValueSource + Send + Sync>;
+
+/// A type erased view of a [`Stat`] (of any M), created with [`Stat::source()`] or [`DerivedStat::source()`].
+///
+/// Used as input for a [`DerivedStat`] (keeps the stat alive), or [`Stat::add_scaled_modifier()`] (doesn't).
+#[derive(Clone)]
+pub struct StatSource(pub(crate) SourceCell);
+
+impl core::fmt::Debug for StatSource {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("StatSource")
+            .field(&self.try_value())
+            .finish()
+    }
+}
+
+impl StatSource {
+    /// Returns the value of the stat
+    /// panics if refcell is borrowed, see [`StatSource::try_value()`]
+    pub fn value(&self) -> f32 {
+        self.try_value().unwrap()
+    }
+
+    /// Same as [`StatSource::value()`], but returns an error instead of panicking
+    pub fn try_value(&self) -> Result<f32, StatError> {
+        Ok(self.0.value_and_version()?.0)
+    }
+
+    fn id(&self) -> *const () {
+        ReferenceCounted::as_ptr(&self.0) as *const ()
+    }
+}
+
+impl<const M: usize> ValueSource for InteriorCell<StatInner<M>> {
+    fn value_and_version(&self) -> Result<(f32, u64), StatError> {
+        let mut inner = borrow_cell(self)?;
+        inner.update_modifiers();
+        inner.update_sources()?;
+        Ok((inner.value(), inner.version()))
+    }
+
+    fn depends_on(&self, target: *const ()) -> Result<bool, StatError> {
+        // compare before locking, the target might be the stat that's asking
+        if core::ptr::eq(self as *const Self as *const (), target) {
+            return Ok(true);
+        }
+        let inner = read_cell(self)?;
+        if let Some(derived) = &inner.derived {
+            for input in derived.inputs.iter() {
+                if input.source.0.depends_on(target)? {
+                    return Ok(true);
+                }
+            }
+        }
+        for scaled in inner.scaled.iter() {
+            if let Some(source) = scaled.source() {
+                if source.depends_on(target)? {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+}
+
+// the formula and inputs of a derived stat, lives inside StatInner so every shared view of the stat follows it
+#[derive(Clone)]
+pub(crate) struct DerivedBase {
+    formula: ReferenceCounted<dyn StatFormula>,
+    inputs: Vec<DerivedInput>,
+    // passed to the formula, in the same order as inputs
+    values: Vec<f32>,
+    // the formula has to run even if no input changed (new formula or restored snapshot)
+    dirty: bool,
+}
+
+#[derive(Clone)]
+struct DerivedInput {
+    source: StatSource,
+    // the version of the input the last time the formula ran
+    version: u64,
+}
+
+impl core::fmt::Debug for DerivedBase {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DerivedBase")
+            .field("values", &self.values)
+            .field("dirty", &self.dirty)
+            .finish()
+    }
+}
+
+impl DerivedBase {
+    fn new(inputs: Vec<StatSource>, formula: impl StatFormula) -> Self {
+        Self {
+            formula: ReferenceCounted::new(formula),
+            values: Vec::with_capacity(inputs.len()),
+            inputs: inputs
+                .into_iter()
+                .map(|source| DerivedInput { source, version: 0 })
+                .collect(),
+            dirty: true,
+        }
+    }
+
+    pub(crate) fn invalidate(&mut self) {
+        self.dirty = true;
+    }
+
+    // returns the new base value, if any input changed since last time
+    pub(crate) fn update(&mut self) -> Result<Option<f32>, StatError> {
+        let mut changed = self.dirty;
+        self.values.resize(self.inputs.len(), 0.0);
+        for (input, value) in self.inputs.iter_mut().zip(self.values.iter_mut()) {
+            let (new_value, version) = input.source.0.value_and_version()?;
+            if changed || input.version != version {
+                input.version = version;
+                *value = new_value;
+                changed = true;
+            }
+        }
+        self.dirty = false;
+        Ok(changed.then(|| (self.formula)(&self.values)))
+    }
+}
+
+/// A stat whose base value is calculated from other stats, it can still have its own modifiers.
+///
+/// The formula runs again when the value of one of the inputs has changed, the inputs are read when you ask for the value.
+/// Use [`DerivedStat::source()`] to make it the input of another derived stat.
+/// A derived stat derefs to a [`Stat`], for all the reading methods.
+/// ```
+/// # use game_stat::prelude::*;
+/// let mut strength: Stat<2> = Stat::new(10.0);
+/// let agility: Stat<2> = Stat::new(5.0);
+/// let mut attack_power: DerivedStat<2> =
+///     DerivedStat::new([strength.source(), agility.source()], |v| 2.0 * v[0] + v[1]);
+/// assert_eq!(attack_power.value(), 25.0);
+///
+/// let _ring = strength.add_modifier(StatModifier::Flat(5.0));
+/// let _sword = attack_power.add_modifier(StatModifier::PercentAdd(1.0));
+/// assert_eq!(attack_power.value(), 70.0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct DerivedStat<const M: usize> {
+    stat: Stat<M>,
+}
+
+impl<const M: usize> Deref for DerivedStat<M> {
+    type Target = Stat<M>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.stat
+    }
+}
+
+impl<const M: usize> Stat<M> {
+    /// Creates a [`StatSource`] to use this stat as the input of a [`DerivedStat`],
+    /// it's a linked view, so the derived stat follows the changes to this stat
+    pub fn source(&self) -> StatSource {
+        StatSource(self.inner.clone())
+    }
+
+    // reading a stat that reads this stat would never finish (or deadlock with the sync feature)
+    pub(crate) fn check_cycle(&self, source: &StatSource) -> Result<(), StatError> {
+        let id = ReferenceCounted::as_ptr(&self.inner) as *const ();
+        if source.id() == id || source.0.depends_on(id)? {
+            return Err(StatError::Cycle);
+        }
+        Ok(())
+    }
+
+    // a derived stat checks its inputs before reading the value
+    fn set_derived(&mut self, derived: DerivedBase) -> Result<(), StatError> {
+        for input in derived.inputs.iter() {
+            self.check_cycle(&input.source)?;
+        }
+        let mut inner = borrow_cell(&self.inner)?;
+        inner.derived = Some(derived);
+        inner.update_sources()
+    }
+}
+
+impl<const M: usize> DerivedStat<M> {
+    /// Creates a stat whose base value is `formula(input values)`
+    /// panics if one of the inputs is borrowed, see [`DerivedStat::try_new()`]
+    pub fn new(inputs: impl IntoIterator<Item = StatSource>, formula: impl StatFormula) -> Self {
+        Self::try_new(inputs, formula).unwrap()
+    }
+
+    /// Same as [`DerivedStat::new()`], but returns an error instead of panicking
+    pub fn try_new(
+        inputs: impl IntoIterator<Item = StatSource>,
+        formula: impl StatFormula,
+    ) -> Result<Self, StatError> {
+        let mut stat = Stat::new(0.0);
+        stat.set_derived(DerivedBase::new(inputs.into_iter().collect(), formula))?;
+        Ok(Self { stat })
+    }
+
+    /// Replaces the inputs and formula
+    /// panics if refcell is borrowed or an input depends on this stat, see [`DerivedStat::try_set_formula()`]
+    pub fn set_formula(
+        &mut self,
+        inputs: impl IntoIterator<Item = StatSource>,
+        formula: impl StatFormula,
+    ) {
+        self.try_set_formula(inputs, formula).unwrap()
+    }
+
+    /// Same as [`DerivedStat::set_formula()`], but returns an error instead of panicking.
+    /// Returns [`StatError::Cycle`] if one of the inputs depends on this stat, the old formula is kept
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// let strength: Stat<2> = Stat::new(10.0);
+    /// let mut attack_power: DerivedStat<2> = DerivedStat::new([strength.source()], |v| v[0] * 2.0);
+    /// let crit_chance: DerivedStat<2> = DerivedStat::new([attack_power.source()], |v| v[0] / 100.0);
+    /// assert_eq!(
+    ///     attack_power.try_set_formula([crit_chance.source()], |v| v[0]),
+    ///     Err(StatError::Cycle)
+    /// );
+    /// assert_eq!(attack_power.value(), 20.0);
+    /// ```
+    pub fn try_set_formula(
+        &mut self,
+        inputs: impl IntoIterator<Item = StatSource>,
+        formula: impl StatFormula,
+    ) -> Result<(), StatError> {
+        self.stat
+            .set_derived(DerivedBase::new(inputs.into_iter().collect(), formula))
+    }
+
+    /// Same as [`Stat::source()`]
+    pub fn source(&self) -> StatSource {
+        self.stat.source()
+    }
+
+    /// Same as [`Stat::add_modifier()`]
+    pub fn add_modifier(&mut self, modifier: StatModifier) -> StatModifierHandle {
+        self.stat.add_modifier(modifier)
+    }
+
+    /// Same as [`Stat::try_add_modifier()`]
+    pub fn try_add_modifier(
+        &mut self,
+        modifier: StatModifier,
+    ) -> Result<StatModifierHandle, StatError> {
+        self.stat.try_add_modifier(modifier)
+    }
+
+    /// Same as [`Stat::add_modifier_with_order()`]
+    pub fn add_modifier_with_order(
+        &mut self,
+        modifier: StatModifier,
+        order: i32,
+    ) -> StatModifierHandle {
+        self.stat.add_modifier_with_order(modifier, order)
+    }
+
+    /// Same as [`Stat::try_add_modifier_with_order()`]
+    pub fn try_add_modifier_with_order(
+        &mut self,
+        modifier: StatModifier,
+        order: i32,
+    ) -> Result<StatModifierHandle, StatError> {
+        self.stat.try_add_modifier_with_order(modifier, order)
+    }
+
+    /// Same as [`Stat::add_scaled_modifier()`]
+    pub fn add_scaled_modifier(
+        &mut self,
+        source: &StatSource,
+        scale: impl ModifierScale,
+    ) -> StatModifierHandle {
+        self.stat.add_scaled_modifier(source, scale)
+    }
+
+    /// Same as [`Stat::try_add_scaled_modifier()`]
+    pub fn try_add_scaled_modifier(
+        &mut self,
+        source: &StatSource,
+        scale: impl ModifierScale,
+    ) -> Result<StatModifierHandle, StatError> {
+        self.stat.try_add_scaled_modifier(source, scale)
+    }
+
+    /// Same as [`Stat::insert_modifier()`]
+    pub fn insert_modifier(&mut self, modifier: StatModifier) -> ModifierKey {
+        self.stat.insert_modifier(modifier)
+    }
+
+    /// Same as [`Stat::try_insert_modifier()`]
+    pub fn try_insert_modifier(
+        &mut self,
+        modifier: StatModifier,
+    ) -> Result<ModifierKey, StatError> {
+        self.stat.try_insert_modifier(modifier)
+    }
+
+    /// Same as [`Stat::insert_modifier_with_order()`]
+    pub fn insert_modifier_with_order(
+        &mut self,
+        modifier: StatModifier,
+        order: i32,
+    ) -> ModifierKey {
+        self.stat.insert_modifier_with_order(modifier, order)
+    }
+
+    /// Same as [`Stat::try_insert_modifier_with_order()`]
+    pub fn try_insert_modifier_with_order(
+        &mut self,
+        modifier: StatModifier,
+        order: i32,
+    ) -> Result<ModifierKey, StatError> {
+        self.stat.try_insert_modifier_with_order(modifier, order)
+    }
+
+    /// Same as [`Stat::remove_modifier()`]
+    pub fn remove_modifier(&mut self, key: ModifierKey) -> bool {
+        self.stat.remove_modifier(key)
+    }
+
+    /// Same as [`Stat::try_remove_modifier()`]
+    pub fn try_remove_modifier(&mut self, key: ModifierKey) -> Result<bool, StatError> {
+        self.stat.try_remove_modifier(key)
+    }
+}
@@ -0,0 +1,214 @@
+//! [Bevy](https://bevyengine.org) integration, enabled with the `bevy` feature (which also enables `sync`)
+//!
+//! * [`StatComponent`] wraps a [`Stat`], [`StatSheet`] is a component as is
+//! * [`StatModifierComponent`] holds a handle, despawning the entity removes the modifier
+//! * [`TimedModifier`] despawns its entity when the time is up, ticked by [`GameStatPlugin`]
+//! * [`StatComponentPlugin`] ticks the durations and stack decay of a [`StatComponent`] (see [`Stat::tick()`]),
+//!   and sends a [`StatChanged`] event whenever its value changes
+//! * [`StatSheetPlugin`] ticks a [`StatSheet`] component
+//!
+//! ```
+//! # use bevy::prelude::*;
+//! # use game_stat::prelude::*;
+//! # use game_stat::ecs::*;
+//! struct Armor;
+//!
+//! let mut app = App::new();
+//! app.add_plugins((MinimalPlugins, GameStatPlugin, StatComponentPlugin::<Armor, 4>::default()));
+//! let player = app.world_mut().spawn(StatComponent::<Armor, 4>::new(10.0)).id();
+//!
+//! let handle = app.world_mut().get_mut::<StatComponent<Armor, 4>>(player).unwrap()
+//!     .add_modifier(StatModifier::Flat(5.0));
+//! app.world_mut().spawn(TimedModifier::new(handle, 30.0));
+//! app.update();
+//! assert_eq!(app.world().get::<StatComponent<Armor, 4>>(player).unwrap().value(), 15.0);
+//! ```
+
+use crate::sheet::StatSheet;
+use crate::stat::{Stat, StatModifierHandle};
+use bevy::prelude::*;
+use core::marker::PhantomData;
+
+/// Ticks [`TimedModifier`]s, add [`StatComponentPlugin`] for each stat type and [`StatSheetPlugin`] for each sheet type
+pub struct GameStatPlugin;
+
+impl Plugin for GameStatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, tick_timed_modifiers);
+    }
+}
+
+/// Ticks [`StatComponent<T, M>`] every frame and sends [`StatChanged<T>`] events for it
+pub struct StatComponentPlugin<T, const M: usize> {
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T, const M: usize> Default for StatComponentPlugin<T, M> {
+    fn default() -> Self {
+        Self {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: 'static, const M: usize> Plugin for StatComponentPlugin<T, M> {
+    fn build(&self, app: &mut App) {
+        app.add_event::<StatChanged<T>>()
+            .add_systems(Update, tick_stats::<T, M>)
+            .add_systems(PostUpdate, send_stat_changed_events::<T, M>);
+    }
+}
+
+/// Ticks [`StatSheet<K, M>`] components every frame, see [`StatSheet::tick()`]
+pub struct StatSheetPlugin<K, const M: usize> {
+    marker: PhantomData<fn() -> K>,
+}
+
+impl<K, const M: usize> Default for StatSheetPlugin<K, M> {
+    fn default() -> Self {
+        Self {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<K: Ord + Send + Sync + 'static, const M: usize> Plugin for StatSheetPlugin<K, M> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, tick_stat_sheets::<K, M>);
+    }
+}
+
+/// A [`Stat`] as a component. `T` is just a marker, so an entity can have several stats (armor, speed...) with the same `M`
+#[derive(Component)]
+pub struct StatComponent<T: 'static, const M: usize> {
+    pub stat: Stat<M>,
+    // the value we last sent a StatChanged event for
+    last_value: Option<f32>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: 'static, const M: usize> StatComponent<T, M> {
+    pub fn new(base_value: f32) -> Self {
+        Self::from(Stat::new(base_value))
+    }
+}
+
+impl<T: 'static, const M: usize> From<Stat<M>> for StatComponent<T, M> {
+    fn from(stat: Stat<M>) -> Self {
+        Self {
+            stat,
+            last_value: None,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: 'static, const M: usize> core::ops::Deref for StatComponent<T, M> {
+    type Target = Stat<M>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.stat
+    }
+}
+
+impl<T: 'static, const M: usize> core::ops::DerefMut for StatComponent<T, M> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.stat
+    }
+}
+
+/// Keeps a modifier alive, despawn the entity (or remove the component) to remove the modifier
+#[derive(Component)]
+pub struct StatModifierComponent(pub StatModifierHandle);
+
+/// Keeps a modifier alive for some seconds, then the entity is despawned by [`GameStatPlugin`].
+/// Spawn one entity per timed modifier, like a buff.
+#[derive(Component)]
+pub struct TimedModifier {
+    pub handle: StatModifierHandle,
+    /// seconds left until the entity is despawned
+    pub remaining: f32,
+}
+
+impl TimedModifier {
+    pub fn new(handle: StatModifierHandle, seconds: f32) -> Self {
+        Self {
+            handle,
+            remaining: seconds,
+        }
+    }
+}
+
+/// Sent when the value of a [`StatComponent<T, M>`] changes, requires a [`StatComponentPlugin<T, M>`]
+#[derive(Event)]
+pub struct StatChanged<T: 'static> {
+    pub entity: Entity,
+    /// None the first time the stat is seen
+    pub old_value: Option<f32>,
+    pub new_value: f32,
+    marker: PhantomData<fn() -> T>,
+}
+
+// handcrafted, T doesn't have to implement Debug
+impl<T: 'static> core::fmt::Debug for StatChanged<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("StatChanged")
+            .field("entity", &self.entity)
+            .field("old_value", &self.old_value)
+            .field("new_value", &self.new_value)
+            .finish()
+    }
+}
+
+fn tick_timed_modifiers(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut timed_modifiers: Query<(Entity, &mut TimedModifier)>,
+) {
+    for (entity, mut timed_modifier) in timed_modifiers.iter_mut() {
+        timed_modifier.remaining -= time.delta_secs();
+        if timed_modifier.remaining <= 0.0 {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+// ticking isn't a change to the component, StatChanged events tell when the value changes
+fn tick_stats<T: 'static, const M: usize>(
+    time: Res<Time>,
+    mut stats: Query<&mut StatComponent<T, M>>,
+) {
+    for mut stat in stats.iter_mut() {
+        stat.bypass_change_detection().tick(time.delta_secs());
+    }
+}
+
+fn tick_stat_sheets<K: Ord + Send + Sync + 'static, const M: usize>(
+    time: Res<Time>,
+    mut sheets: Query<&mut StatSheet<K, M>>,
+) {
+    for mut sheet in sheets.iter_mut() {
+        sheet.bypass_change_detection().tick(time.delta_secs());
+    }
+}
+
+// a stat changes without touching the component (a handle is dropped somewhere), so every stat is checked each frame
+fn send_stat_changed_events<T: 'static, const M: usize>(
+    mut events: EventWriter<StatChanged<T>>,
+    mut stats: Query<(Entity, &mut StatComponent<T, M>)>,
+) {
+    for (entity, mut stat) in stats.iter_mut() {
+        let new_value = stat.value();
+        if stat.last_value != Some(new_value) {
+            // only our own bookkeeping, not a real change to the component
+            let stat = stat.bypass_change_detection();
+            events.send(StatChanged {
+                entity,
+                old_value: stat.last_value,
+                new_value,
+                marker: PhantomData,
+            });
+            stat.last_value = Some(new_value);
+        }
+    }
+}
@@ -0,0 +1,31 @@
+/// Returned by the `try_` methods on [`super::Stat`], instead of panicking
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StatError {
+    /// The stat is already borrowed, most likely you're using a stat from inside a call on the same stat
+    Borrowed,
+    /// With the sync feature: another thread panicked while using the stat
+    Poisoned,
+    /// With [`super::CapacityPolicy::Strict`]: the stat already holds the maximum amount of modifiers
+    CapacityExceeded { capacity: usize },
+    /// A [`super::DerivedStat`] can't use itself as an input, not even through other derived stats
+    Cycle,
+}
+
+impl core::fmt::Display for StatError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            StatError::Borrowed => write!(f, "stat is already borrowed"),
+            StatError::Poisoned => {
+                write!(f, "stat lock is poisoned, a thread panicked while using it")
+            }
+            StatError::CapacityExceeded { capacity } => {
+                write!(f, "stat can't hold more than {capacity} modifiers")
+            }
+            StatError::Cycle => write!(f, "derived stat depends on itself"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for StatError {}
@@ -0,0 +1,629 @@
+use crate::derived::{DerivedStat, StatSource};
+use crate::error::StatError;
+use crate::graph::StatGraph;
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// A parsed formula like `base * (1 + str / 100) + flat_bonus`, written by designers in data files.
+///
+/// Supports numbers, stats by name, `+ - * /`, comparisons (`< <= > >= == !=`, 1 if true and 0 if false),
+/// parentheses and the functions `min(a, b, ...)`, `max(a, b, ...)`, `clamp(x, min, max)`, `abs(x)`
+/// and `if(condition, then, else)` for piecewise formulas.
+/// Use it as the formula of a [`DerivedStat::from_formula()`] or [`StatGraph::insert_formula()`].
+/// ```
+/// # use game_stat::Formula;
+/// let formula = Formula::parse("base * (1 + str / 100) + flat_bonus").unwrap();
+/// assert_eq!(formula.variables(), ["base", "str", "flat_bonus"]);
+/// assert_eq!(formula.evaluate(&[10.0, 50.0, 5.0]), 20.0);
+///
+/// let error = Formula::parse("max(str, agi").unwrap_err();
+/// assert_eq!(error.to_string(), "expected `)` at 12..12");
+/// ```
+#[derive(Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "String", into = "String")
+)]
+pub struct Formula {
+    source: String,
+    expr: Expr,
+    variables: Vec<String>,
+    // where each variable is used first, for error messages
+    variable_spans: Vec<Range<usize>>,
+}
+
+/// Returned when a [`Formula`] can't be parsed, or uses a stat that doesn't exist (or can't be read)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormulaError {
+    message: String,
+    span: Range<usize>,
+    stat_error: Option<StatError>,
+}
+
+impl FormulaError {
+    fn new(message: impl Into<String>, span: Range<usize>) -> Self {
+        Self {
+            message: message.into(),
+            span,
+            stat_error: None,
+        }
+    }
+
+    // the stats are used by the whole formula
+    fn stat(error: StatError, source: &str) -> Self {
+        Self {
+            message: error.to_string(),
+            span: 0..source.len(),
+            stat_error: Some(error),
+        }
+    }
+
+    /// What went wrong, without the span
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Where in the formula it went wrong, in bytes
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// Set when [`DerivedStat::from_formula()`] couldn't read a stat (the span is the whole formula)
+    pub fn stat_error(&self) -> Option<StatError> {
+        self.stat_error
+    }
+}
+
+impl core::fmt::Display for FormulaError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{} at {}..{}",
+            self.message, self.span.start, self.span.end
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FormulaError {}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(f32),
+    // index into the variables
+    Variable(usize),
+    Negate(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+#[derive(Copy, Clone, Debug)]
+enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Function {
+    Min,
+    Max,
+    Clamp,
+    Abs,
+    If,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "min" => Self::Min,
+            "max" => Self::Max,
+            "clamp" => Self::Clamp,
+            "abs" => Self::Abs,
+            "if" => Self::If,
+            _ => return None,
+        })
+    }
+
+    // None if any amount (at least one) is fine
+    fn argument_count(&self) -> Option<usize> {
+        match self {
+            Self::Min | Self::Max => None,
+            Self::Abs => Some(1),
+            Self::Clamp | Self::If => Some(3),
+        }
+    }
+}
+
+impl Formula {
+    /// Parses the formula, the error tells where in `source` it went wrong
+    pub fn parse(source: &str) -> Result<Self, FormulaError> {
+        let mut parser = Parser {
+            source,
+            tokens: tokenize(source)?,
+            position: 0,
+            depth: 0,
+            variables: Vec::new(),
+            variable_spans: Vec::new(),
+        };
+        let expr = parser.expression()?;
+        if let Some(token) = parser.peek() {
+            return Err(FormulaError::new(
+                "expected an operator",
+                token.span.clone(),
+            ));
+        }
+        Ok(Self {
+            source: source.to_string(),
+            expr,
+            variables: parser.variables,
+            variable_spans: parser.variable_spans,
+        })
+    }
+
+    /// The formula as it was written
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The names of the stats used by the formula, in the order they first appear
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    /// Calculates the formula, `values` are in the same order as [`Formula::variables()`], missing values are 0
+    pub fn evaluate(&self, values: &[f32]) -> f32 {
+        self.expr.evaluate(values)
+    }
+
+    // unknown variables are reported with the span of their first use
+    fn resolve<T>(
+        &self,
+        mut resolve: impl FnMut(&str) -> Option<T>,
+    ) -> Result<Vec<T>, FormulaError> {
+        self.variables
+            .iter()
+            .zip(self.variable_spans.iter())
+            .map(|(name, span)| {
+                resolve(name).ok_or_else(|| {
+                    FormulaError::new(alloc::format!("unknown stat `{name}`"), span.clone())
+                })
+            })
+            .collect()
+    }
+}
+
+impl core::fmt::Display for Formula {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl core::str::FromStr for Formula {
+    type Err = FormulaError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        Self::parse(source)
+    }
+}
+
+impl TryFrom<String> for Formula {
+    type Error = FormulaError;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Self::parse(&source)
+    }
+}
+
+impl From<Formula> for String {
+    fn from(formula: Formula) -> Self {
+        formula.source
+    }
+}
+
+impl Expr {
+    fn evaluate(&self, values: &[f32]) -> f32 {
+        match self {
+            Expr::Number(number) => *number,
+            Expr::Variable(index) => values.get(*index).copied().unwrap_or(0.0),
+            Expr::Negate(expr) => -expr.evaluate(values),
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (left.evaluate(values), right.evaluate(values));
+                let bool_value = |b: bool| if b { 1.0 } else { 0.0 };
+                match op {
+                    BinaryOp::Add => left + right,
+                    BinaryOp::Subtract => left - right,
+                    BinaryOp::Multiply => left * right,
+                    BinaryOp::Divide => left / right,
+                    BinaryOp::Less => bool_value(left < right),
+                    BinaryOp::LessEqual => bool_value(left <= right),
+                    BinaryOp::Greater => bool_value(left > right),
+                    BinaryOp::GreaterEqual => bool_value(left >= right),
+                    BinaryOp::Equal => bool_value(left == right),
+                    BinaryOp::NotEqual => bool_value(left != right),
+                }
+            }
+            Expr::Call(function, arguments) => {
+                // the amount of arguments is checked when parsing
+                let argument = |index: usize| arguments[index].evaluate(values);
+                match function {
+                    Function::Min => arguments[1..]
+                        .iter()
+                        .fold(argument(0), |min, a| min.min(a.evaluate(values))),
+                    Function::Max => arguments[1..]
+                        .iter()
+                        .fold(argument(0), |max, a| max.max(a.evaluate(values))),
+                    Function::Clamp => argument(0).max(argument(1)).min(argument(2)),
+                    Function::Abs => {
+                        let value = argument(0);
+                        if value < 0.0 {
+                            -value
+                        } else {
+                            value
+                        }
+                    }
+                    // only the branch that is used is calculated
+                    Function::If if argument(0) != 0.0 => argument(1),
+                    Function::If => argument(2),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Number(f32),
+    Identifier,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    EqualEqual,
+    NotEqual,
+    OpenParen,
+    CloseParen,
+    Comma,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: TokenKind,
+    span: Range<usize>,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, FormulaError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let two_chars = |chars: &mut core::iter::Peekable<core::str::CharIndices>,
+                         kind: TokenKind| {
+            chars.next();
+            (kind, start + 2)
+        };
+        let (kind, end) = match c {
+            c if c.is_whitespace() => continue,
+            '+' => (TokenKind::Plus, start + 1),
+            '-' => (TokenKind::Minus, start + 1),
+            '*' => (TokenKind::Star, start + 1),
+            '/' => (TokenKind::Slash, start + 1),
+            '(' => (TokenKind::OpenParen, start + 1),
+            ')' => (TokenKind::CloseParen, start + 1),
+            ',' => (TokenKind::Comma, start + 1),
+            '<' if matches!(chars.peek(), Some((_, '='))) => {
+                two_chars(&mut chars, TokenKind::LessEqual)
+            }
+            '<' => (TokenKind::Less, start + 1),
+            '>' if matches!(chars.peek(), Some((_, '='))) => {
+                two_chars(&mut chars, TokenKind::GreaterEqual)
+            }
+            '>' => (TokenKind::Greater, start + 1),
+            '=' if matches!(chars.peek(), Some((_, '='))) => {
+                two_chars(&mut chars, TokenKind::EqualEqual)
+            }
+            '!' if matches!(chars.peek(), Some((_, '='))) => {
+                two_chars(&mut chars, TokenKind::NotEqual)
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut end = start + 1;
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_ascii_digit() || c == '.') {
+                        break;
+                    }
+                    end = i + 1;
+                    chars.next();
+                }
+                let number = source[start..end]
+                    .parse()
+                    .map_err(|_| FormulaError::new("invalid number", start..end))?;
+                (TokenKind::Number(number), end)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                (TokenKind::Identifier, end)
+            }
+            c => {
+                return Err(FormulaError::new(
+                    alloc::format!("unexpected character `{c}`"),
+                    start..start + c.len_utf8(),
+                ))
+            }
+        };
+        tokens.push(Token {
+            kind,
+            span: start..end,
+        });
+    }
+    Ok(tokens)
+}
+
+// deeper formulas are an error instead of a stack overflow
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+    // how many parentheses, calls and `-` we're in
+    depth: usize,
+    variables: Vec<String>,
+    variable_spans: Vec<Range<usize>>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    // consumes the next token if it's one of the operators
+    fn operator(&mut self, operators: &[(TokenKind, BinaryOp)]) -> Option<BinaryOp> {
+        let token = self.peek()?;
+        let (_, op) = operators.iter().find(|(kind, _)| *kind == token.kind)?;
+        self.position += 1;
+        Some(*op)
+    }
+
+    // the span right after the last token, for "expected ..." errors
+    fn end_span(&self) -> Range<usize> {
+        match self.peek() {
+            Some(token) => token.span.clone(),
+            None => self.source.len()..self.source.len(),
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind, what: &str) -> Result<Token, FormulaError> {
+        match self.peek() {
+            Some(token) if token.kind == kind => Ok(self.next().unwrap()),
+            _ => Err(FormulaError::new(
+                alloc::format!("expected {what}"),
+                self.end_span(),
+            )),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, FormulaError> {
+        self.binary(0)
+    }
+
+    // parse recurses, so the depth is limited. `span` is what opens the nested expression
+    fn nested(
+        &mut self,
+        span: Range<usize>,
+        parse: fn(&mut Self) -> Result<Expr, FormulaError>,
+    ) -> Result<Expr, FormulaError> {
+        if self.depth == MAX_DEPTH {
+            return Err(FormulaError::new(
+                alloc::format!("nested more than {MAX_DEPTH} levels deep"),
+                span,
+            ));
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    // lowest precedence first
+    fn binary(&mut self, level: usize) -> Result<Expr, FormulaError> {
+        const LEVELS: &[&[(TokenKind, BinaryOp)]] = &[
+            &[
+                (TokenKind::Less, BinaryOp::Less),
+                (TokenKind::LessEqual, BinaryOp::LessEqual),
+                (TokenKind::Greater, BinaryOp::Greater),
+                (TokenKind::GreaterEqual, BinaryOp::GreaterEqual),
+                (TokenKind::EqualEqual, BinaryOp::Equal),
+                (TokenKind::NotEqual, BinaryOp::NotEqual),
+            ],
+            &[
+                (TokenKind::Plus, BinaryOp::Add),
+                (TokenKind::Minus, BinaryOp::Subtract),
+            ],
+            &[
+                (TokenKind::Star, BinaryOp::Multiply),
+                (TokenKind::Slash, BinaryOp::Divide),
+            ],
+        ];
+        let Some(operators) = LEVELS.get(level) else {
+            return self.unary();
+        };
+        let mut left = self.binary(level + 1)?;
+        while let Some(op) = self.operator(operators) {
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, FormulaError> {
+        if let Some(minus) = self.peek().filter(|token| token.kind == TokenKind::Minus) {
+            let span = minus.span.clone();
+            self.next();
+            return Ok(Expr::Negate(Box::new(self.nested(span, Self::unary)?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, FormulaError> {
+        let span = self.end_span();
+        let Some(token) = self.next() else {
+            return Err(FormulaError::new("expected an expression", span));
+        };
+        match token.kind {
+            TokenKind::Number(number) => Ok(Expr::Number(number)),
+            TokenKind::OpenParen => {
+                let expr = self.nested(token.span, Self::expression)?;
+                self.expect(TokenKind::CloseParen, "`)`")?;
+                Ok(expr)
+            }
+            TokenKind::Identifier
+                if self
+                    .peek()
+                    .is_some_and(|next| next.kind == TokenKind::OpenParen) =>
+            {
+                self.call(token)
+            }
+            TokenKind::Identifier => Ok(Expr::Variable(self.variable(token.span))),
+            _ => Err(FormulaError::new("expected an expression", token.span)),
+        }
+    }
+
+    fn call(&mut self, name: Token) -> Result<Expr, FormulaError> {
+        let function_name = &self.source[name.span.clone()];
+        let function = Function::from_name(function_name).ok_or_else(|| {
+            FormulaError::new(
+                alloc::format!("unknown function `{function_name}`"),
+                name.span.clone(),
+            )
+        })?;
+        self.expect(TokenKind::OpenParen, "`(`")?;
+        let mut arguments = Vec::new();
+        if !self
+            .peek()
+            .is_some_and(|token| token.kind == TokenKind::CloseParen)
+        {
+            arguments.push(self.nested(name.span.clone(), Self::expression)?);
+            while self
+                .peek()
+                .is_some_and(|token| token.kind == TokenKind::Comma)
+            {
+                self.next();
+                arguments.push(self.nested(name.span.clone(), Self::expression)?);
+            }
+        }
+        let close = self.expect(TokenKind::CloseParen, "`)`")?;
+
+        let expected = function.argument_count();
+        if arguments.is_empty() || expected.is_some_and(|count| count != arguments.len()) {
+            let expected = match expected {
+                Some(count) => alloc::format!("{count}"),
+                None => String::from("at least 1"),
+            };
+            return Err(FormulaError::new(
+                alloc::format!(
+                    "`{function_name}` takes {expected} arguments, found {}",
+                    arguments.len()
+                ),
+                name.span.start..close.span.end,
+            ));
+        }
+        Ok(Expr::Call(function, arguments))
+    }
+
+    fn variable(&mut self, span: Range<usize>) -> usize {
+        let name = &self.source[span.clone()];
+        match self.variables.iter().position(|variable| variable == name) {
+            Some(index) => index,
+            None => {
+                self.variables.push(name.to_string());
+                self.variable_spans.push(span);
+                self.variables.len() - 1
+            }
+        }
+    }
+}
+
+impl<const M: usize> DerivedStat<M> {
+    /// Creates a derived stat from a [`Formula`], `resolve` finds the stat for every name used in the formula
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// # use game_stat::Formula;
+    /// let strength: Stat<2> = Stat::new(50.0);
+    /// let formula = Formula::parse("10 * (1 + str / 100)").unwrap();
+    /// let attack_power: DerivedStat<2> = DerivedStat::from_formula(&formula, |name| match name {
+    ///     "str" => Some(strength.source()),
+    ///     _ => None,
+    /// })
+    /// .unwrap();
+    /// assert_eq!(attack_power.value(), 15.0);
+    /// ```
+    pub fn from_formula(
+        formula: &Formula,
+        resolve: impl FnMut(&str) -> Option<StatSource>,
+    ) -> Result<Self, FormulaError> {
+        let inputs = formula.resolve(resolve)?;
+        let source = formula.source();
+        let formula = formula.clone();
+        Self::try_new(inputs, move |values: &[f32]| formula.evaluate(values))
+            .map_err(|error| FormulaError::stat(error, source))
+    }
+}
+
+impl<K: Ord + Clone, const M: usize> StatGraph<K, M> {
+    /// Same as [`StatGraph::insert_derived()`], the inputs are the names used in the formula
+    /// panics if the stat would depend on itself, see [`StatGraph::try_insert_formula()`]
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// # use game_stat::Formula;
+    /// let mut graph: StatGraph<String, 2> = StatGraph::new();
+    /// graph.insert("str".to_string(), Stat::new(50.0));
+    /// graph.insert_formula("attack_power".to_string(), &"10 * (1 + str / 100)".parse().unwrap());
+    /// assert_eq!(graph.value("attack_power"), Some(15.0));
+    /// ```
+    pub fn insert_formula(&mut self, key: K, formula: &Formula)
+    where
+        K: for<'a> From<&'a str>,
+    {
+        self.try_insert_formula(key, formula).unwrap()
+    }
+
+    /// Same as [`StatGraph::insert_formula()`], but returns [`StatError::Cycle`] instead of panicking
+    pub fn try_insert_formula(&mut self, key: K, formula: &Formula) -> Result<(), StatError>
+    where
+        K: for<'a> From<&'a str>,
+    {
+        let inputs: Vec<K> = formula
+            .variables()
+            .iter()
+            .map(|name| K::from(name))
+            .collect();
+        let formula = formula.clone();
+        self.try_insert_derived(key, inputs, move |values: &[f32]| formula.evaluate(values))
+    }
+}
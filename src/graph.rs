@@ -0,0 +1,378 @@
+use crate::derived::StatFormula;
+use crate::error::StatError;
+use crate::stat::Stat;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+
+/// Stats looked up by key, where some stats are calculated from other stats in the graph.
+///
+/// The graph knows which stats depend on which, changing a stat through [`StatGraph::get_mut()`] marks
+/// everything downstream dirty. Formulas only run for dirty stats, either when the stat is read with
+/// [`StatGraph::value()`] or for the whole graph with [`StatGraph::update()`] (in topological order, at the end of a frame).
+/// Changes the graph doesn't see (a dropped [`super::StatModifierHandle`], a shared view of a stat) are noticed when reading.
+///
+/// Inputs that aren't in the graph count as 0.
+/// ```
+/// # use game_stat::prelude::*;
+/// let mut graph: StatGraph<&str, 2> = StatGraph::new();
+/// graph.insert("strength", Stat::new(10.0));
+/// graph.insert("agility", Stat::new(20.0));
+/// graph.insert_derived("attack_power", ["strength", "agility"], |v| 2.0 * v[0] + v[1]);
+/// graph.insert_derived("crit_chance", ["agility"], |v| 0.05 + v[0] / 100.0);
+/// assert_eq!(graph.value("attack_power"), Some(40.0));
+/// graph.update();
+///
+/// let _ring = graph.get_mut("strength").unwrap().add_modifier(StatModifier::Flat(5.0));
+/// assert!(graph.is_dirty("attack_power"));
+/// assert!(!graph.is_dirty("crit_chance"));
+/// graph.update();
+/// assert_eq!(graph.get("attack_power").unwrap().value(), 50.0);
+/// ```
+pub struct StatGraph<K: Ord + Clone, const M: usize> {
+    nodes: BTreeMap<K, GraphNode<K, M>>,
+    // the stats that use a key as input, a key doesn't have to be in the graph to be used
+    dependents: BTreeMap<K, Vec<K>>,
+    // inputs before the stats that use them, None when stats or formulas were added/removed
+    order: Option<Vec<K>>,
+    // passed to the formulas, reused
+    values: Vec<f32>,
+}
+
+struct GraphNode<K, const M: usize> {
+    stat: Stat<M>,
+    formula: Option<GraphFormula<K>>,
+    // the version of the stat the last time its dependents were told about it
+    version: u64,
+    // the formula has to run again
+    dirty: bool,
+}
+
+struct GraphFormula<K> {
+    inputs: Vec<K>,
+    formula: Box<dyn StatFormula>,
+}
+
+impl<K: Ord + Clone + core::fmt::Debug, const M: usize> core::fmt::Debug for StatGraph<K, M> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_map()
+            .entries(self.nodes.iter().map(|(key, node)| (key, &node.stat)))
+            .finish()
+    }
+}
+
+impl<K: Ord + Clone, const M: usize> Default for StatGraph<K, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord + Clone, const M: usize> StatGraph<K, M> {
+    pub fn new() -> Self {
+        Self {
+            nodes: BTreeMap::new(),
+            dependents: BTreeMap::new(),
+            order: None,
+            values: Vec::new(),
+        }
+    }
+
+    /// Adds a stat, returns the old stat if there already was one with this key (its formula is removed)
+    pub fn insert(&mut self, key: K, stat: Stat<M>) -> Option<Stat<M>> {
+        let old = self.remove(&key);
+        self.nodes.insert(
+            key.clone(),
+            GraphNode {
+                stat,
+                formula: None,
+                version: 0,
+                dirty: false,
+            },
+        );
+        // remove() only resets the order if the key was already there
+        self.order = None;
+        self.mark_dependents_dirty(&key);
+        old
+    }
+
+    /// Makes `key` a stat whose base value is `formula(input values)`, the inputs are other keys in the graph.
+    /// If there already is a stat with this key it keeps its modifiers, otherwise a new stat is created.
+    /// panics if the stat would depend on itself, see [`StatGraph::try_insert_derived()`]
+    pub fn insert_derived(
+        &mut self,
+        key: K,
+        inputs: impl IntoIterator<Item = K>,
+        formula: impl StatFormula,
+    ) {
+        self.try_insert_derived(key, inputs, formula).unwrap()
+    }
+
+    /// Same as [`StatGraph::insert_derived()`], but returns [`StatError::Cycle`] instead of panicking.
+    /// The graph is unchanged when an error is returned
+    pub fn try_insert_derived(
+        &mut self,
+        key: K,
+        inputs: impl IntoIterator<Item = K>,
+        formula: impl StatFormula,
+    ) -> Result<(), StatError> {
+        let inputs: Vec<K> = inputs.into_iter().collect();
+        if inputs.iter().any(|input| self.depends_on(input, &key)) {
+            return Err(StatError::Cycle);
+        }
+
+        self.remove_edges(&key);
+        for input in inputs.iter() {
+            self.dependents
+                .entry(input.clone())
+                .or_default()
+                .push(key.clone());
+        }
+        let formula = Some(GraphFormula {
+            inputs,
+            formula: Box::new(formula),
+        });
+        match self.nodes.get_mut(&key) {
+            Some(node) => {
+                node.formula = formula;
+                node.dirty = true;
+            }
+            None => {
+                self.nodes.insert(
+                    key.clone(),
+                    GraphNode {
+                        stat: Stat::new(0.0),
+                        formula,
+                        version: 0,
+                        dirty: true,
+                    },
+                );
+            }
+        }
+        self.order = None;
+        self.mark_dependents_dirty(&key);
+        Ok(())
+    }
+
+    /// Removes the stat and its formula, stats using it as input now read 0
+    pub fn remove<Q: Ord + ?Sized>(&mut self, key: &Q) -> Option<Stat<M>>
+    where
+        K: Borrow<Q>,
+    {
+        let (key, _) = self.nodes.get_key_value(key)?;
+        let key = key.clone();
+        self.remove_edges(&key);
+        self.mark_dependents_dirty(&key);
+        self.order = None;
+        self.nodes.remove::<K>(&key).map(|node| node.stat)
+    }
+
+    /// The stat, its value might be out of date if it's derived, see [`StatGraph::value()`]
+    pub fn get<Q: Ord + ?Sized>(&self, key: &Q) -> Option<&Stat<M>>
+    where
+        K: Borrow<Q>,
+    {
+        self.nodes.get(key).map(|node| &node.stat)
+    }
+
+    /// Everything that depends on this stat is marked dirty.
+    /// The base value of a derived stat is replaced the next time its formula runs
+    pub fn get_mut<Q: Ord + ?Sized>(&mut self, key: &Q) -> Option<&mut Stat<M>>
+    where
+        K: Borrow<Q>,
+    {
+        let (key, _) = self.nodes.get_key_value(key)?;
+        let key = key.clone();
+        self.mark_dependents_dirty(&key);
+        self.nodes.get_mut::<K>(&key).map(|node| &mut node.stat)
+    }
+
+    pub fn contains<Q: Ord + ?Sized>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+    {
+        self.nodes.contains_key(key)
+    }
+
+    /// Returns true if the formula of this stat has to run again
+    pub fn is_dirty<Q: Ord + ?Sized>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+    {
+        self.nodes.get(key).is_some_and(|node| node.dirty)
+    }
+
+    /// Returns the value of a stat, only the formulas this stat depends on run (if they are dirty)
+    /// panics if refcell is borrowed, see [`StatGraph::try_value()`]
+    pub fn value<Q: Ord + ?Sized>(&mut self, key: &Q) -> Option<f32>
+    where
+        K: Borrow<Q>,
+    {
+        self.try_value(key).unwrap()
+    }
+
+    /// Same as [`StatGraph::value()`], but returns an error instead of panicking
+    pub fn try_value<Q: Ord + ?Sized>(&mut self, key: &Q) -> Result<Option<f32>, StatError>
+    where
+        K: Borrow<Q>,
+    {
+        let Some((key, _)) = self.nodes.get_key_value(key) else {
+            return Ok(None);
+        };
+        let key = key.clone();
+        self.refresh(&key)?;
+        self.nodes
+            .get::<K>(&key)
+            .unwrap()
+            .stat
+            .try_value()
+            .map(Some)
+    }
+
+    /// Runs the formula of every dirty stat, inputs first
+    /// panics if refcell is borrowed, see [`StatGraph::try_update()`]
+    pub fn update(&mut self) {
+        self.try_update().unwrap()
+    }
+
+    /// Same as [`StatGraph::update()`], but returns an error instead of panicking
+    pub fn try_update(&mut self) -> Result<(), StatError> {
+        let order = match self.order.take() {
+            Some(order) => order,
+            None => self.topological_order(),
+        };
+        let result = order.iter().try_for_each(|key| self.sync(key));
+        self.order = Some(order);
+        result
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &Stat<M>)> {
+        self.nodes.iter().map(|(key, node)| (key, &node.stat))
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // makes sure all inputs of key are up to date, then key itself
+    fn refresh(&mut self, key: &K) -> Result<(), StatError> {
+        // taken out while the inputs are refreshed, no cycles so nobody needs it
+        let formula = match self.nodes.get_mut(key) {
+            Some(node) => node.formula.take(),
+            None => return Ok(()),
+        };
+        let result = match &formula {
+            Some(formula) => formula
+                .inputs
+                .iter()
+                .try_for_each(|input| self.refresh(input)),
+            None => Ok(()),
+        };
+        self.nodes.get_mut(key).unwrap().formula = formula;
+        result?;
+        self.sync(key)
+    }
+
+    // runs the formula if dirty, and tells the dependents if the stat changed. The inputs have to be up to date
+    fn sync(&mut self, key: &K) -> Result<(), StatError> {
+        let Some(node) = self.nodes.get(key) else {
+            return Ok(());
+        };
+        if let Some(formula) = node.formula.as_ref().filter(|_| node.dirty) {
+            self.values.clear();
+            for input in formula.inputs.iter() {
+                let value = match self.nodes.get(input) {
+                    Some(input) => input.stat.try_value()?,
+                    None => 0.0,
+                };
+                self.values.push(value);
+            }
+            let base_value = (formula.formula)(&self.values);
+            let node = self.nodes.get_mut(key).unwrap();
+            node.stat.try_set_base_value(base_value)?;
+            node.dirty = false;
+        }
+
+        let node = self.nodes.get_mut(key).unwrap();
+        let version = node.stat.try_version()?;
+        if node.version != version {
+            node.version = version;
+            self.mark_dependents_dirty(key);
+        }
+        Ok(())
+    }
+
+    fn mark_dependents_dirty(&mut self, key: &K) {
+        let mut stack: Vec<&K> = Vec::new();
+        stack.push(key);
+        while let Some(key) = stack.pop() {
+            for dependent in self.dependents.get(key).into_iter().flatten() {
+                if let Some(node) = self.nodes.get_mut(dependent) {
+                    // the dependents of a dirty stat are already dirty
+                    if !node.dirty {
+                        node.dirty = true;
+                        stack.push(dependent);
+                    }
+                }
+            }
+        }
+    }
+
+    // true if key is target, or reads target through its inputs
+    fn depends_on(&self, key: &K, target: &K) -> bool {
+        key == target
+            || self
+                .nodes
+                .get(key)
+                .and_then(|node| node.formula.as_ref())
+                .is_some_and(|formula| {
+                    formula
+                        .inputs
+                        .iter()
+                        .any(|input| self.depends_on(input, target))
+                })
+    }
+
+    // removes key from the dependents of its inputs
+    fn remove_edges(&mut self, key: &K) {
+        let Some(formula) = self.nodes.get_mut(key).and_then(|node| node.formula.take()) else {
+            return;
+        };
+        for input in formula.inputs.iter() {
+            if let Some(dependents) = self.dependents.get_mut(input) {
+                dependents.retain(|dependent| dependent != key);
+                if dependents.is_empty() {
+                    self.dependents.remove(input);
+                }
+            }
+        }
+    }
+
+    fn topological_order(&self) -> Vec<K> {
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut visited = BTreeSet::new();
+        for key in self.nodes.keys() {
+            self.visit(key, &mut visited, &mut order);
+        }
+        order
+    }
+
+    fn visit<'a>(&'a self, key: &'a K, visited: &mut BTreeSet<&'a K>, order: &mut Vec<K>) {
+        if !visited.insert(key) {
+            return;
+        }
+        let Some(node) = self.nodes.get(key) else {
+            return;
+        };
+        if let Some(formula) = &node.formula {
+            for input in formula.inputs.iter() {
+                self.visit(input, visited, order);
+            }
+        }
+        order.push(key.clone());
+    }
+}
@@ -0,0 +1,144 @@
+use crate::cell::{borrow_cell, read_cell};
+use crate::curve::Curve;
+use crate::error::StatError;
+use crate::stat::Stat;
+use alloc::vec::Vec;
+
+/// How the base value of a stat grows with the level, see [`Stat::with_growth()`].
+/// Levels start at 1, level 0 counts as level 1
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Growth {
+    /// `base + per_level * (level - 1)`
+    Linear { base: f32, per_level: f32 },
+    /// `base * rate^(level - 1)`, a rate of 1.1 is 10% more every level
+    Exponential { base: f32, rate: f32 },
+    /// The base value for level 1, 2, 3... Past the end of the table the last value is used, an empty table is 0
+    Table(Vec<f32>),
+    /// `curve(level)`
+    Curve(Curve),
+}
+
+impl Growth {
+    /// The base value at this level
+    /// ```
+    /// # use game_stat::Growth;
+    /// let warrior_health = Growth::Linear { base: 100.0, per_level: 12.0 };
+    /// assert_eq!(warrior_health.value(1), 100.0);
+    /// assert_eq!(warrior_health.value(11), 220.0);
+    /// ```
+    pub fn value(&self, level: u32) -> f32 {
+        let steps = level.max(1) - 1;
+        match self {
+            Growth::Linear { base, per_level } => base + per_level * steps as f32,
+            Growth::Exponential { base, rate } => base * powi(*rate, steps),
+            Growth::Table(table) => table
+                .get(steps as usize)
+                .or(table.last())
+                .copied()
+                .unwrap_or(0.0),
+            Growth::Curve(curve) => curve.sample(level.max(1) as f32),
+        }
+    }
+}
+
+// no powi without std
+fn powi(mut base: f32, mut exponent: u32) -> f32 {
+    let mut result = 1.0;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result *= base;
+        }
+        base *= base;
+        exponent >>= 1;
+    }
+    result
+}
+
+// the growth of a stat and its current level
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct LevelGrowth {
+    growth: Growth,
+    level: u32,
+}
+
+impl LevelGrowth {
+    pub(crate) fn level(&self) -> u32 {
+        self.level
+    }
+
+    pub(crate) fn set_level(&mut self, level: u32) {
+        self.level = level;
+    }
+
+    pub(crate) fn base_value(&self) -> f32 {
+        self.growth.value(self.level)
+    }
+}
+
+impl<const M: usize> Stat<M> {
+    /// Creates a stat whose base value comes from `growth` at `level`, change the level with [`Stat::set_level()`]
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// let mut strength: Stat<2> = Stat::with_growth(Growth::Table(vec![10.0, 12.0, 15.0]), 1);
+    /// let _ring = strength.add_modifier(StatModifier::Flat(1.0));
+    /// assert_eq!(strength.value(), 11.0);
+    /// strength.set_level(3);
+    /// assert_eq!(strength.value(), 16.0);
+    /// ```
+    pub fn with_growth(growth: Growth, level: u32) -> Self {
+        let mut stat = Self::new(0.0);
+        stat.set_growth(growth, level);
+        stat
+    }
+
+    /// Replaces the growth (like when the character changes class), the base value is calculated right away
+    /// panics if refcell is borrowed, see [`Stat::try_set_growth()`]
+    pub fn set_growth(&mut self, growth: Growth, level: u32) {
+        self.try_set_growth(growth, level).unwrap()
+    }
+
+    /// Same as [`Stat::set_growth()`], but returns an error instead of panicking
+    pub fn try_set_growth(&mut self, growth: Growth, level: u32) -> Result<(), StatError> {
+        let growth = LevelGrowth { growth, level };
+        let mut inner = borrow_cell(&self.inner)?;
+        inner.base_value = growth.base_value();
+        inner.growth = Some(growth);
+        inner.update_modifiers();
+        inner.calculate_internal_value();
+        Ok(())
+    }
+
+    /// Changes the level, the base value and the value are calculated right away.
+    /// Returns false if the stat has no growth.
+    /// A base value set with [`Stat::set_base_value()`] only lasts until the next level change
+    /// panics if refcell is borrowed, see [`Stat::try_set_level()`]
+    pub fn set_level(&mut self, level: u32) -> bool {
+        self.try_set_level(level).unwrap()
+    }
+
+    /// Same as [`Stat::set_level()`], but returns an error instead of panicking
+    pub fn try_set_level(&mut self, level: u32) -> Result<bool, StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        let Some(growth) = &mut inner.growth else {
+            return Ok(false);
+        };
+        growth.set_level(level);
+        let base_value = growth.base_value();
+        inner.base_value = base_value;
+        inner.update_modifiers();
+        inner.calculate_internal_value();
+        Ok(true)
+    }
+
+    /// The level of a stat with growth
+    /// panics if refcell is borrowed
+    pub fn level(&self) -> Option<u32> {
+        read_cell(&self.inner)
+            .unwrap()
+            .growth
+            .as_ref()
+            .map(LevelGrowth::level)
+    }
+}
@@ -10,19 +10,89 @@
 //! }
 //! println!("armor_stat is: {}, It should be 10!", armor_stat.value());
 //! ```
-//! * [`Stat<2>`] is a stat that can hold a maximum of 2 modifiers. (the modifiers is an array internally, carefully select a sensible value)
+//! * [`Stat<2>`] is a stat that can hold 2 modifiers without allocating. (the modifiers is an array internally, carefully select a sensible value)
+//!   More modifiers are moved to the heap, unless the stat is created with [`Stat::new_strict()`]
 //! * ```armor_stat.value()``` returns our stat value based on what modifiers are active.
 //! * We add a [`StatModifier`], it is valid as long as the [`StatModifierHandle`] that is returned from [`Stat::add_modifier()`] exists, which is why our value goes back to 10 when it gets dropped from the stack
 //!
+//! If you'd rather not hold on to handles, [`Stat::insert_modifier()`] returns a [`ModifierKey`] instead, a plain [`Copy`] id
+//! you remove explicitly with [`Stat::remove_modifier()`]. Keyed modifiers are also included when serializing a stat.
+//!
+//! Every method that can panic has a `try_` version returning a [`StatError`] instead, like [`Stat::try_value()`]
+//!
 //! # crate features:
+//! **std** (default): implements `std::error::Error` and uses `std::sync::Mutex` with the sync feature
+//!
+//! **alloc**: disable default features and enable this for `no_std` targets, game_stat always needs an allocator
+//!
 //! **sync**: if Stat is needed in a multithreaded environment, enable this
+//!
+//! **spin**: sync, but with a spin lock from the `spin` crate instead of `std::sync::Mutex`, works without std
+//!
+//! **bevy**: components, a plugin and events for [Bevy](https://bevyengine.org), see the `ecs` module (enables sync)
+//!
+//! **serde**: serialize stats, and [`ModifierDefinitions`], modifier templates for data files
+//!
+//! **ron**, **json**, **toml**: load [`ModifierDefinitions`] from these formats
+//!
+//! **rwlock**: sync, but with a RwLock instead of a Mutex. Reading a stat from many threads at once doesn't block,
+//! adding modifiers gets a bit slower. Combine with spin to get a spin RwLock
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(feature = "alloc"))]
+compile_error!("game_stat needs either the std or alloc feature");
+#[cfg(all(feature = "sync", not(feature = "std"), not(feature = "spin")))]
+compile_error!("the sync feature needs a Mutex, enable either the std or spin feature");
+
+extern crate alloc;
 
+mod aggregation;
+mod batch;
+mod cell;
+mod curve;
+#[cfg(feature = "serde")]
+mod definitions;
+mod derived;
+#[cfg(feature = "bevy")]
+pub mod ecs;
+mod error;
+mod formula;
+mod graph;
+mod growth;
 mod modifier;
+#[cfg(feature = "serde")]
+mod reload;
+mod scaled;
+mod sheet;
+mod stacking;
 mod stat;
+pub use crate::aggregation::*;
+pub use crate::batch::*;
+pub use crate::curve::*;
+#[cfg(feature = "serde")]
+pub use crate::definitions::*;
+pub use crate::derived::*;
+pub use crate::error::*;
+pub use crate::formula::*;
+pub use crate::graph::*;
+pub use crate::growth::*;
 pub use crate::modifier::*;
+#[cfg(feature = "serde")]
+pub use crate::reload::*;
+pub use crate::scaled::*;
+pub use crate::sheet::*;
+pub use crate::stacking::*;
 pub use crate::stat::*;
 
 pub mod prelude {
-    pub use crate::modifier::StatModifier;
-    pub use crate::stat::{Stat, StatModifierHandle};
+    pub use crate::aggregation::Aggregation;
+    pub use crate::derived::DerivedStat;
+    pub use crate::error::StatError;
+    pub use crate::graph::StatGraph;
+    pub use crate::growth::Growth;
+    pub use crate::modifier::{ModifierTier, StatModifier};
+    pub use crate::sheet::StatSheet;
+    pub use crate::stacking::{StackableModifier, StackingModifier, StackingPolicy};
+    pub use crate::stat::{ModifierKey, Stat, StatModifierHandle};
 }
@@ -1,5 +1,8 @@
 /// Used to transform the base value of a [`super::Stat`]
-#[derive(Copy, Clone, Debug)]
+///
+/// With the default orders a stat is evaluated in stages:
+/// `(base + Flat) * (1 + sum of Increased) * (1 + More) * (1 + More)...`, then the caps
+#[derive(Copy, Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum StatModifier {
     /// Add or subtract flat value. ```StatModifier::Flat(-1.0)``` means it will **subtract -1.0**
@@ -8,6 +11,33 @@ pub enum StatModifier {
     PercentAdd(f32),
     /// Direct multiplication. StatModifier::```PercentMultiply(0.5)``` the value is **halved**
     PercentMultiply(f32),
+    /// The value can't go above this. Caps apply after the multipliers by default, use
+    /// [`super::Stat::add_modifier_with_order()`] to put it somewhere else, modifiers with a higher order still apply after it
+    HardCap(f32),
+    /// Past the threshold only `rate` of the value counts. ```StatModifier::SoftCap { threshold: 100.0, rate: 0.5 }``` turns 140 into 120
+    SoftCap { threshold: f32, rate: f32 },
+    /// `max * value / (value + k)`, the value gets closer to `max` but never reaches it.
+    /// With `k` 100, 100 armor gives half of `max`. `k` has to be more than 0
+    DiminishingReturns { max: f32, k: f32 },
+    /// "20% increased", all Increased modifiers with the same order are added up into one multiplier
+    /// (unless the order group uses another [`super::Aggregation`]). Two times ```StatModifier::Increased(0.2)``` is **+40%**
+    Increased(f32),
+    /// "20% more", every More modifier multiplies on its own, after the increased ones.
+    /// Two times ```StatModifier::More(0.2)``` is **1.2 * 1.2**
+    More(f32),
+}
+
+/// Which part of a stat a modifier belongs to, for a character screen showing "Strength 50 (+23)".
+/// `Base` modifiers are applied first and make up [`super::Stat::base_total()`],
+/// `Bonus` modifiers are applied on top of that and make up [`super::Stat::bonus()`]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ModifierTier {
+    /// Permanent modifiers, like talents. A `PercentAdd` here only scales the base ("+10% base strength")
+    Base,
+    /// Temporary modifiers, like gear and buffs. Modifiers are bonus unless they're moved to the base tier
+    #[default]
+    Bonus,
 }
 
 impl Default for StatModifier {
@@ -23,6 +53,42 @@ impl StatModifier {
             StatModifier::Flat(v) => *value += v,
             StatModifier::PercentAdd(v) => *value *= 1.0f32 + v,
             StatModifier::PercentMultiply(v) => *value *= v,
+            StatModifier::HardCap(max) => *value = value.min(*max),
+            StatModifier::SoftCap { threshold, rate } => {
+                if *value > *threshold {
+                    *value = threshold + (*value - threshold) * rate;
+                }
+            }
+            // abs keeps it away from dividing by 0, a negative value gets the same curve downwards
+            StatModifier::DiminishingReturns { max, k } => {
+                *value = max * *value / (value.abs() + k)
+            }
+            StatModifier::Increased(v) | StatModifier::More(v) => *value *= 1.0 + v,
+        }
+    }
+
+    /// The modifier `stacks` times over: the magnitude of Flat, PercentAdd and Increased is multiplied by `stacks`,
+    /// PercentMultiply and More multiply once per stack. 0 stacks doesn't change the value, caps don't stack
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// assert_eq!(StatModifier::PercentAdd(0.03).stacked(4), StatModifier::PercentAdd(0.12));
+    /// assert_eq!(StatModifier::PercentMultiply(0.5).stacked(2), StatModifier::PercentMultiply(0.25));
+    /// ```
+    pub fn stacked(&self, stacks: u32) -> StatModifier {
+        match self {
+            StatModifier::Flat(v) => StatModifier::Flat(v * stacks as f32),
+            StatModifier::PercentAdd(v) => StatModifier::PercentAdd(v * stacks as f32),
+            // no powi without std
+            StatModifier::PercentMultiply(v) => {
+                StatModifier::PercentMultiply((0..stacks).fold(1.0, |total, _| total * v))
+            }
+            StatModifier::Increased(v) => StatModifier::Increased(v * stacks as f32),
+            StatModifier::More(v) => {
+                StatModifier::More((0..stacks).fold(1.0, |total, _| total * (1.0 + v)) - 1.0)
+            }
+            StatModifier::HardCap(_)
+            | StatModifier::SoftCap { .. }
+            | StatModifier::DiminishingReturns { .. } => *self,
         }
     }
 
@@ -30,8 +96,11 @@ impl StatModifier {
     pub fn default_order(&self) -> i32 {
         match self {
             StatModifier::Flat(_) => 0,
-            StatModifier::PercentAdd(_) => 1,
-            StatModifier::PercentMultiply(_) => 2,
+            StatModifier::PercentAdd(_) | StatModifier::Increased(_) => 1,
+            StatModifier::PercentMultiply(_) | StatModifier::More(_) => 2,
+            StatModifier::HardCap(_)
+            | StatModifier::SoftCap { .. }
+            | StatModifier::DiminishingReturns { .. } => 3,
         }
     }
 }
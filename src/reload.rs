@@ -0,0 +1,245 @@
+use crate::cell::{borrow_cell, InteriorCell, ReferenceCounted, Weak};
+use crate::definitions::{ModifierDefinitions, ModifierTemplate};
+use crate::error::StatError;
+use crate::modifier::StatModifier;
+use crate::stat::{ModifierKey, Stat, StatInner, StatModifierHandle, StatModifierHandleTag};
+use alloc::collections::BTreeSet;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+// lets the definitions change a modifier without knowing the M of the stat
+pub(crate) trait ModifierTarget {
+    fn retune_modifier(
+        &self,
+        key: ModifierKey,
+        modifier: StatModifier,
+        order: i32,
+    ) -> Result<bool, StatError>;
+}
+
+impl<const M: usize> ModifierTarget for InteriorCell<StatInner<M>> {
+    fn retune_modifier(
+        &self,
+        key: ModifierKey,
+        modifier: StatModifier,
+        order: i32,
+    ) -> Result<bool, StatError> {
+        Ok(borrow_cell(self)?.retune_modifier(key, modifier, order))
+    }
+}
+
+#[cfg(not(feature = "sync"))]
+type WeakTarget = Weak<dyn ModifierTarget>;
+#[cfg(feature = "sync")]
+type WeakTarget = Weak<dyn ModifierTarget + Send + Sync>;
+
+// a modifier created by ModifierDefinitions::instantiate()
+#[derive(Clone, Debug)]
+pub(crate) struct TemplateInstance {
+    template: String,
+    // the key of the stat in the sheet, for the report
+    stat_key: String,
+    stat: WeakTarget,
+    handle: Weak<StatModifierHandleTag>,
+    key: ModifierKey,
+}
+
+impl TemplateInstance {
+    fn is_alive(&self) -> bool {
+        self.handle.strong_count() > 0 && self.stat.strong_count() > 0
+    }
+
+    // returns false if the modifier is gone
+    fn retune(&self, template: &ModifierTemplate) -> Result<bool, StatError> {
+        match self.stat.upgrade() {
+            Some(stat) => stat.retune_modifier(self.key, template.modifier, template.order()),
+            None => Ok(false),
+        }
+    }
+}
+
+/// What changed when [`ModifierDefinitions`] were reloaded
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReloadReport {
+    /// New templates
+    pub added: Vec<String>,
+    /// Templates that are gone, their live modifiers are left alone
+    pub removed: Vec<String>,
+    /// Templates that changed in any way
+    pub changed: Vec<String>,
+    /// The stats (keys in the sheet) with live modifiers that were updated
+    pub affected_stats: Vec<String>,
+    /// How many live modifiers were updated
+    pub updated_modifiers: usize,
+}
+
+impl ReloadReport {
+    /// Returns true if nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl ModifierDefinitions {
+    pub(crate) fn track_instance<const M: usize>(
+        &mut self,
+        template: &str,
+        stat_key: &str,
+        stat: &Stat<M>,
+        handle: &StatModifierHandle,
+    ) {
+        // forget dropped modifiers before the list would have to grow
+        if self.instances.len() == self.instances.capacity() {
+            self.instances.retain(TemplateInstance::is_alive);
+        }
+        let stat: WeakTarget = ReferenceCounted::downgrade(&stat.inner) as WeakTarget;
+        self.instances.push(TemplateInstance {
+            template: template.to_string(),
+            stat_key: stat_key.to_string(),
+            stat,
+            handle: ReferenceCounted::downgrade(handle),
+            key: handle.key(),
+        });
+    }
+
+    /// Replaces the templates, the live modifiers created from a changed template get its new modifier and order
+    /// (a changed `stat` or `duration` only affects new modifiers).
+    /// panics if a stat is borrowed, see [`ModifierDefinitions::try_reload()`]
+    /// ```
+    /// # #[cfg(feature = "json")] {
+    /// # use game_stat::prelude::*;
+    /// # use game_stat::ModifierDefinitions;
+    /// let mut definitions = ModifierDefinitions::from_json_str(
+    ///     r#"{ "fire_sword_bonus": { "stat": "attack", "modifier": { "Flat": 5.0 } } }"#,
+    /// ).unwrap();
+    /// let mut sheet: StatSheet<&str, 2> = StatSheet::new();
+    /// sheet.insert("attack", Stat::new(10.0));
+    /// let _handle = definitions.instantiate("fire_sword_bonus", &mut sheet).unwrap();
+    ///
+    /// let report = definitions.reload(ModifierDefinitions::from_json_str(
+    ///     r#"{ "fire_sword_bonus": { "stat": "attack", "modifier": { "Flat": 8.0 } } }"#,
+    /// ).unwrap());
+    /// assert_eq!(report.affected_stats, ["attack"]);
+    /// assert_eq!(sheet.value("attack"), Some(18.0));
+    /// # }
+    /// ```
+    pub fn reload(&mut self, definitions: ModifierDefinitions) -> ReloadReport {
+        self.try_reload(definitions).unwrap()
+    }
+
+    /// Same as [`ModifierDefinitions::reload()`], but returns an error instead of panicking.
+    /// The old templates and live modifiers are kept when an error is returned, so the same definitions can be reloaded again
+    pub fn try_reload(
+        &mut self,
+        definitions: ModifierDefinitions,
+    ) -> Result<ReloadReport, StatError> {
+        let old = core::mem::replace(&mut self.templates, definitions.templates);
+        let mut report = ReloadReport::default();
+        for (name, template) in self.templates.iter() {
+            match old.get(name) {
+                None => report.added.push(name.clone()),
+                Some(old) if old != template => report.changed.push(name.clone()),
+                Some(_) => {}
+            }
+        }
+        report.removed = old
+            .keys()
+            .filter(|name| !self.templates.contains_key(*name))
+            .cloned()
+            .collect();
+
+        let mut affected_stats = BTreeSet::new();
+        // rolled back if a later modifier can't be updated
+        let mut retuned_instances = Vec::new();
+        let mut result = Ok(());
+        let templates = &self.templates;
+        self.instances.retain(|instance| {
+            if !instance.is_alive() {
+                return false;
+            }
+            let Some(template) = templates.get(&instance.template) else {
+                return true;
+            };
+            let unchanged = old.get(&instance.template).is_some_and(|old| {
+                old.modifier == template.modifier && old.order() == template.order()
+            });
+            if unchanged || result.is_err() {
+                return true;
+            }
+            match instance.retune(template) {
+                Ok(retuned) => {
+                    if retuned {
+                        affected_stats.insert(instance.stat_key.clone());
+                        report.updated_modifiers += 1;
+                        retuned_instances.push(instance.clone());
+                    }
+                    retuned
+                }
+                Err(error) => {
+                    result = Err(error);
+                    true
+                }
+            }
+        });
+        if let Err(error) = result {
+            for instance in retuned_instances.iter() {
+                if let Some(template) = old.get(&instance.template) {
+                    // this stat could be borrowed a moment ago, nothing to do if it can't be now
+                    let _ = instance.retune(template);
+                }
+            }
+            self.templates = old;
+            return Err(error);
+        }
+        report.affected_stats = affected_stats.into_iter().collect();
+        Ok(report)
+    }
+
+    /// Loads the file again and reloads the templates, see [`ModifierDefinitions::try_reload()`]
+    #[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+    pub fn reload_file(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<ReloadReport, ReloadError> {
+        let definitions = Self::load(path)?;
+        Ok(self.try_reload(definitions)?)
+    }
+}
+
+/// Returned by [`ModifierDefinitions::reload_file()`]
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReloadError {
+    /// The file couldn't be loaded, nothing changed
+    Definition(crate::DefinitionError),
+    /// A live modifier couldn't be updated, see [`ModifierDefinitions::try_reload()`]
+    Stat(StatError),
+}
+
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+impl core::fmt::Display for ReloadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ReloadError::Definition(error) => error.fmt(f),
+            ReloadError::Stat(error) => error.fmt(f),
+        }
+    }
+}
+
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+impl std::error::Error for ReloadError {}
+
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+impl From<crate::DefinitionError> for ReloadError {
+    fn from(error: crate::DefinitionError) -> Self {
+        ReloadError::Definition(error)
+    }
+}
+
+#[cfg(any(feature = "ron", feature = "json", feature = "toml"))]
+impl From<StatError> for ReloadError {
+    fn from(error: StatError) -> Self {
+        ReloadError::Stat(error)
+    }
+}
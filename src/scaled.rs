@@ -0,0 +1,185 @@
+use crate::cell::{borrow_cell, ReferenceCounted};
+use crate::derived::{SourceCell, StatSource, WeakSourceCell};
+use crate::error::StatError;
+use crate::modifier::StatModifier;
+use crate::stat::{ModifierKey, Stat, StatInner, StatModifierHandle};
+
+/// Turns the value of the source stat into a modifier, see [`Stat::add_scaled_modifier()`].
+/// Any `Fn(f32) -> StatModifier` works (with the sync feature it also has to be `Send + Sync`)
+#[cfg(not(feature = "sync"))]
+pub trait ModifierScale: Fn(f32) -> StatModifier + 'static {}
+#[cfg(not(feature = "sync"))]
+impl<F: Fn(f32) -> StatModifier + 'static> ModifierScale for F {}
+
+/// Turns the value of the source stat into a modifier, see [`Stat::add_scaled_modifier()`].
+/// Any `Fn(f32) -> StatModifier` works (with the sync feature it also has to be `Send + Sync`)
+#[cfg(feature = "sync")]
+pub trait ModifierScale: Fn(f32) -> StatModifier + Send + Sync + 'static {}
+#[cfg(feature = "sync")]
+impl<F: Fn(f32) -> StatModifier + Send + Sync + 'static> ModifierScale for F {}
+
+// the modifier with the same key is rewritten every time the source changes
+#[derive(Clone)]
+pub(crate) struct ScaledModifier {
+    key: ModifierKey,
+    source: WeakSourceCell,
+    scale: ReferenceCounted<dyn ModifierScale>,
+    // the version of the source the last time the modifier was scaled, None to force it
+    version: Option<u64>,
+}
+
+impl core::fmt::Debug for ScaledModifier {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ScaledModifier")
+            .field("key", &self.key)
+            .field("version", &self.version)
+            .finish()
+    }
+}
+
+impl ScaledModifier {
+    pub(crate) fn key(&self) -> ModifierKey {
+        self.key
+    }
+
+    pub(crate) fn source(&self) -> Option<SourceCell> {
+        self.source.upgrade()
+    }
+
+    pub(crate) fn invalidate(&mut self) {
+        self.version = None;
+    }
+
+    // returns the new modifier if the source changed, once the source is dropped the modifier keeps its last value
+    pub(crate) fn update(&mut self) -> Result<Option<StatModifier>, StatError> {
+        let Some(source) = self.source() else {
+            return Ok(None);
+        };
+        let (value, version) = source.value_and_version()?;
+        if self.version == Some(version) {
+            return Ok(None);
+        }
+        self.version = Some(version);
+        Ok(Some((self.scale)(value)))
+    }
+}
+
+impl<const M: usize> Stat<M> {
+    /// Add a modifier calculated from the value of another stat, it follows the changes to that stat.
+    /// Uses the default order of the modifier `scale` returns right now.
+    /// Only a weak reference to `source` is kept, if the source stat is dropped the modifier keeps its last value.
+    /// panics if refcell is borrowed, the capacity is exceeded in strict mode,
+    /// or `source` depends on this stat, see [`Stat::try_add_scaled_modifier()`]
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// let mut max_health: Stat<2> = Stat::new(100.0);
+    /// let mut armor: Stat<2> = Stat::new(10.0);
+    /// // gain armor equal to 20% of max health
+    /// let _handle = armor.add_scaled_modifier(&max_health.source(), |health| StatModifier::Flat(health * 0.2));
+    /// assert_eq!(armor.value(), 30.0);
+    /// max_health.set_base_value(200.0);
+    /// assert_eq!(armor.value(), 50.0);
+    /// ```
+    pub fn add_scaled_modifier(
+        &mut self,
+        source: &StatSource,
+        scale: impl ModifierScale,
+    ) -> StatModifierHandle {
+        self.try_add_scaled_modifier(source, scale).unwrap()
+    }
+
+    /// Same as [`Stat::add_scaled_modifier()`], but returns an error instead of panicking.
+    /// Returns [`StatError::Cycle`] if `source` depends on this stat
+    pub fn try_add_scaled_modifier(
+        &mut self,
+        source: &StatSource,
+        scale: impl ModifierScale,
+    ) -> Result<StatModifierHandle, StatError> {
+        let (modifier, version) = self.scale_source(source, &scale)?;
+        let mut inner = borrow_cell(&self.inner)?;
+        let handle = inner.push_handle_modifier(modifier, modifier.default_order())?;
+        inner.push_scaled(handle.key(), source, scale, version);
+        inner.calculate_internal_value();
+        Ok(handle)
+    }
+
+    /// panics if refcell is borrowed, the capacity is exceeded in strict mode,
+    /// or `source` depends on this stat, see [`Stat::try_add_scaled_modifier_with_order()`]
+    pub fn add_scaled_modifier_with_order(
+        &mut self,
+        source: &StatSource,
+        scale: impl ModifierScale,
+        order: i32,
+    ) -> StatModifierHandle {
+        self.try_add_scaled_modifier_with_order(source, scale, order)
+            .unwrap()
+    }
+
+    /// Same as [`Stat::add_scaled_modifier_with_order()`], but returns an error instead of panicking
+    pub fn try_add_scaled_modifier_with_order(
+        &mut self,
+        source: &StatSource,
+        scale: impl ModifierScale,
+        order: i32,
+    ) -> Result<StatModifierHandle, StatError> {
+        let (modifier, version) = self.scale_source(source, &scale)?;
+        let mut inner = borrow_cell(&self.inner)?;
+        let handle = inner.push_handle_modifier(modifier, order)?;
+        inner.push_scaled(handle.key(), source, scale, version);
+        inner.calculate_internal_value();
+        Ok(handle)
+    }
+
+    /// Same as [`Stat::add_scaled_modifier()`], but the modifier lives until it's removed with [`Stat::remove_modifier()`]
+    /// panics if refcell is borrowed, the capacity is exceeded in strict mode,
+    /// or `source` depends on this stat, see [`Stat::try_insert_scaled_modifier()`]
+    pub fn insert_scaled_modifier(
+        &mut self,
+        source: &StatSource,
+        scale: impl ModifierScale,
+    ) -> ModifierKey {
+        self.try_insert_scaled_modifier(source, scale).unwrap()
+    }
+
+    /// Same as [`Stat::insert_scaled_modifier()`], but returns an error instead of panicking
+    pub fn try_insert_scaled_modifier(
+        &mut self,
+        source: &StatSource,
+        scale: impl ModifierScale,
+    ) -> Result<ModifierKey, StatError> {
+        let (modifier, version) = self.scale_source(source, &scale)?;
+        let mut inner = borrow_cell(&self.inner)?;
+        let key = inner.push_keyed_modifier(modifier, modifier.default_order())?;
+        inner.push_scaled(key, source, scale, version);
+        inner.calculate_internal_value();
+        Ok(key)
+    }
+
+    // reads the source before self is borrowed
+    fn scale_source(
+        &self,
+        source: &StatSource,
+        scale: &impl ModifierScale,
+    ) -> Result<(StatModifier, u64), StatError> {
+        self.check_cycle(source)?;
+        let (value, version) = source.0.value_and_version()?;
+        Ok((scale(value), version))
+    }
+}
+
+impl<const M: usize> StatInner<M> {
+    fn push_scaled(
+        &mut self,
+        key: ModifierKey,
+        source: &StatSource,
+        scale: impl ModifierScale,
+        version: u64,
+    ) {
+        self.scaled.push(ScaledModifier {
+            key,
+            source: ReferenceCounted::downgrade(&source.0),
+            scale: ReferenceCounted::new(scale),
+            version: Some(version),
+        });
+    }
+}
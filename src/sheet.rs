@@ -0,0 +1,114 @@
+use crate::stat::Stat;
+use alloc::collections::BTreeMap;
+use core::borrow::Borrow;
+
+/// A collection of stats looked up by key, like a character sheet
+/// ```
+/// # use game_stat::prelude::*;
+/// let mut sheet: StatSheet<&str, 2> = StatSheet::new();
+/// sheet.insert("strength", Stat::new(10.0));
+/// let _handle = sheet.get_mut("strength").unwrap().add_modifier(StatModifier::Flat(5.0));
+/// assert_eq!(sheet.value("strength"), Some(15.0));
+/// ```
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "bevy", derive(bevy::prelude::Component))]
+pub struct StatSheet<K: Ord, const M: usize> {
+    stats: BTreeMap<K, Stat<M>>,
+}
+
+impl<K: Ord, const M: usize> Default for StatSheet<K, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord, const M: usize> FromIterator<(K, Stat<M>)> for StatSheet<K, M> {
+    fn from_iter<I: IntoIterator<Item = (K, Stat<M>)>>(iter: I) -> Self {
+        Self {
+            stats: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<K: Ord, const M: usize> StatSheet<K, M> {
+    pub fn new() -> Self {
+        Self {
+            stats: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a stat, returns the old stat if there already was one with this key
+    pub fn insert(&mut self, key: K, stat: Stat<M>) -> Option<Stat<M>> {
+        self.stats.insert(key, stat)
+    }
+
+    pub fn remove<Q: Ord + ?Sized>(&mut self, key: &Q) -> Option<Stat<M>>
+    where
+        K: Borrow<Q>,
+    {
+        self.stats.remove(key)
+    }
+
+    pub fn get<Q: Ord + ?Sized>(&self, key: &Q) -> Option<&Stat<M>>
+    where
+        K: Borrow<Q>,
+    {
+        self.stats.get(key)
+    }
+
+    pub fn get_mut<Q: Ord + ?Sized>(&mut self, key: &Q) -> Option<&mut Stat<M>>
+    where
+        K: Borrow<Q>,
+    {
+        self.stats.get_mut(key)
+    }
+
+    pub fn contains<Q: Ord + ?Sized>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+    {
+        self.stats.contains_key(key)
+    }
+
+    /// Returns the value of a stat, see [`Stat::value()`]
+    /// panics if refcell is borrowed
+    pub fn value<Q: Ord + ?Sized>(&self, key: &Q) -> Option<f32>
+    where
+        K: Borrow<Q>,
+    {
+        self.stats.get(key).map(Stat::value)
+    }
+
+    /// Ticks every stat, see [`Stat::tick()`]
+    /// panics if refcell is borrowed
+    pub fn tick(&mut self, seconds: f32) {
+        for stat in self.stats.values_mut() {
+            stat.tick(seconds);
+        }
+    }
+
+    /// Changes the level of every stat with growth, see [`Stat::set_level()`]
+    /// panics if refcell is borrowed
+    pub fn set_level(&mut self, level: u32) {
+        for stat in self.stats.values_mut() {
+            stat.set_level(level);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &Stat<M>)> {
+        self.stats.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut Stat<M>)> {
+        self.stats.iter_mut()
+    }
+
+    pub fn len(&self) -> usize {
+        self.stats.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stats.is_empty()
+    }
+}
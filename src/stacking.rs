@@ -0,0 +1,471 @@
+use crate::cell::{borrow_cell, read_cell};
+use crate::error::StatError;
+use crate::modifier::StatModifier;
+use crate::stat::{ModifierKey, Stat, StatInner, StatModifierHandle};
+use alloc::borrow::Cow;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Decides what happens when a modifier with the same id is applied again, see [`Stat::apply_stacking()`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StackingPolicy {
+    /// Applying it again does nothing while the modifier is active
+    Unique,
+    /// Applying it again restarts the duration, the magnitude stays the same
+    Refresh,
+    /// Every application is its own modifier, up to this many. At the cap the oldest one is replaced
+    Stack(u32),
+    /// Every application is kept with its own duration, but only the strongest one is applied
+    Strongest,
+    /// The new application replaces the old one
+    Newest,
+}
+
+/// A modifier with an identity, applying the same id twice follows the [`StackingPolicy`].
+/// Like a "Poison" debuff that should only be applied once.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StackingModifier {
+    pub id: Cow<'static, str>,
+    pub modifier: StatModifier,
+    pub order: i32,
+    pub policy: StackingPolicy,
+    /// seconds, counted down by [`Stat::tick()`]. None lasts until it's removed
+    pub duration: Option<f32>,
+}
+
+impl StackingModifier {
+    /// Uses the default order of the modifier and lasts until it's removed
+    pub fn new(
+        id: impl Into<Cow<'static, str>>,
+        modifier: StatModifier,
+        policy: StackingPolicy,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            modifier,
+            order: modifier.default_order(),
+            policy,
+            duration: None,
+        }
+    }
+
+    pub fn with_order(mut self, order: i32) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// The modifier is removed by [`Stat::tick()`] after this many seconds
+    pub fn with_duration(mut self, seconds: f32) -> Self {
+        self.duration = Some(seconds);
+        self
+    }
+}
+
+/// Returned by [`Stat::apply_stacking()`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stacked {
+    /// A new modifier was added, remove it early with [`Stat::remove_modifier()`]
+    Added(ModifierKey),
+    /// [`StackingPolicy::Refresh`] restarted the duration of this modifier
+    Refreshed(ModifierKey),
+    /// [`StackingPolicy::Unique`], the modifier was already active
+    Ignored,
+}
+
+// the modifiers applied with the same id, they live in StatInner::modifiers like keyed modifiers
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct StackGroup {
+    id: Cow<'static, str>,
+    policy: StackingPolicy,
+    // oldest first
+    keys: Vec<ModifierKey>,
+}
+
+/// One modifier with a stack count, created with [`Stat::add_stackable_modifier()`].
+/// The modifier applied is `per_stack` times the count, see [`StatModifier::stacked()`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StackableModifier {
+    pub per_stack: StatModifier,
+    pub order: i32,
+    /// the count it starts with
+    pub stacks: u32,
+    pub max_stacks: Option<u32>,
+    /// seconds until one stack is lost, counted by [`Stat::tick()`]. Adding stacks restarts it
+    pub decay: Option<f32>,
+}
+
+impl StackableModifier {
+    /// Starts with one stack, no maximum and no decay, uses the default order of the modifier
+    pub fn new(per_stack: StatModifier) -> Self {
+        Self {
+            per_stack,
+            order: per_stack.default_order(),
+            stacks: 1,
+            max_stacks: None,
+            decay: None,
+        }
+    }
+
+    pub fn with_order(mut self, order: i32) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn with_stacks(mut self, stacks: u32) -> Self {
+        self.stacks = stacks;
+        self
+    }
+
+    pub fn with_max_stacks(mut self, max_stacks: u32) -> Self {
+        self.max_stacks = Some(max_stacks);
+        self
+    }
+
+    /// Loses one stack every `seconds`
+    pub fn with_decay(mut self, seconds: f32) -> Self {
+        self.decay = Some(seconds);
+        self
+    }
+
+    fn clamp(&self, stacks: u32) -> u32 {
+        self.max_stacks.map_or(stacks, |max| stacks.min(max))
+    }
+}
+
+// the stack count of a modifier, the modifier itself lives in StatInner::modifiers
+#[derive(Clone, Debug)]
+pub(crate) struct StackCounter {
+    key: ModifierKey,
+    stackable: StackableModifier,
+    // seconds since the last stack was gained or lost
+    elapsed: f32,
+}
+
+// how far the modifier moves the value, so a debuff can be the strongest too
+fn strength(modifier: &StatModifier) -> f32 {
+    match modifier {
+        StatModifier::Flat(v)
+        | StatModifier::PercentAdd(v)
+        | StatModifier::Increased(v)
+        | StatModifier::More(v) => v.abs(),
+        StatModifier::PercentMultiply(v) => (v - 1.0).abs(),
+        // caps are all equally strong, the newest one wins
+        StatModifier::HardCap(_)
+        | StatModifier::SoftCap { .. }
+        | StatModifier::DiminishingReturns { .. } => 0.0,
+    }
+}
+
+impl<const M: usize> Stat<M> {
+    /// Adds the modifier, unless a modifier with the same id is active, then its [`StackingPolicy`] decides.
+    /// The policy of the latest application is used for the id.
+    /// panics if refcell is borrowed or the capacity is exceeded in strict mode, see [`Stat::try_apply_stacking()`]
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// let mut health_regen: Stat<4> = Stat::new(10.0);
+    /// let poison = StackingModifier::new("poison", StatModifier::Flat(-2.0), StackingPolicy::Stack(2))
+    ///     .with_duration(5.0);
+    /// health_regen.apply_stacking(poison.clone());
+    /// health_regen.apply_stacking(poison.clone());
+    /// health_regen.apply_stacking(poison);
+    /// assert_eq!(health_regen.stack_count("poison"), 2);
+    /// assert_eq!(health_regen.value(), 6.0);
+    /// health_regen.tick(5.0);
+    /// assert_eq!(health_regen.value(), 10.0);
+    /// ```
+    pub fn apply_stacking(&mut self, modifier: StackingModifier) -> Stacked {
+        self.try_apply_stacking(modifier).unwrap()
+    }
+
+    /// Same as [`Stat::apply_stacking()`], but returns an error instead of panicking
+    pub fn try_apply_stacking(&mut self, modifier: StackingModifier) -> Result<Stacked, StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        let stacked = inner.push_stacking(modifier)?;
+        if stacked != Stacked::Ignored {
+            inner.calculate_internal_value();
+        }
+        Ok(stacked)
+    }
+
+    /// How many modifiers with this id are active (including the ones [`StackingPolicy::Strongest`] doesn't apply)
+    /// panics if refcell is borrowed
+    pub fn stack_count(&self, id: &str) -> usize {
+        let inner = read_cell(&self.inner).unwrap();
+        inner
+            .stacks
+            .iter()
+            .find(|group| group.id == id)
+            .map_or(0, |group| group.keys.len())
+    }
+
+    /// Removes every modifier with this id, returns how many were removed
+    /// panics if refcell is borrowed, see [`Stat::try_remove_stack()`]
+    pub fn remove_stack(&mut self, id: &str) -> usize {
+        self.try_remove_stack(id).unwrap()
+    }
+
+    /// Same as [`Stat::remove_stack()`], but returns an error instead of panicking
+    pub fn try_remove_stack(&mut self, id: &str) -> Result<usize, StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        let Some(index) = inner.stacks.iter().position(|group| group.id == id) else {
+            return Ok(0);
+        };
+        let group = inner.stacks.remove(index);
+        let removed = group
+            .keys
+            .iter()
+            .filter(|key| inner.remove_modifier(**key))
+            .count();
+        inner.calculate_internal_value();
+        Ok(removed)
+    }
+}
+
+impl<const M: usize> Stat<M> {
+    /// Add a modifier with a stack count, like a buff that gains stacks on hit.
+    /// The modifier lives as long as the handle, also at 0 stacks.
+    /// panics if refcell is borrowed or the capacity is exceeded in strict mode, see [`Stat::try_add_stackable_modifier()`]
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// let mut attack_speed: Stat<2> = Stat::new(1.0);
+    /// let frenzy = attack_speed.add_stackable_modifier(
+    ///     StackableModifier::new(StatModifier::PercentAdd(0.5)).with_max_stacks(3).with_decay(1.0),
+    /// );
+    /// attack_speed.add_stacks(frenzy.key(), 5);
+    /// assert_eq!(attack_speed.stacks(frenzy.key()), Some(3));
+    /// assert_eq!(attack_speed.value(), 2.5);
+    /// attack_speed.tick(1.0);
+    /// assert_eq!(attack_speed.value(), 2.0);
+    /// ```
+    pub fn add_stackable_modifier(&mut self, stackable: StackableModifier) -> StatModifierHandle {
+        self.try_add_stackable_modifier(stackable).unwrap()
+    }
+
+    /// Same as [`Stat::add_stackable_modifier()`], but returns an error instead of panicking
+    pub fn try_add_stackable_modifier(
+        &mut self,
+        mut stackable: StackableModifier,
+    ) -> Result<StatModifierHandle, StatError> {
+        stackable.stacks = stackable.clamp(stackable.stacks);
+        let mut inner = borrow_cell(&self.inner)?;
+        let handle = inner.push_handle_modifier(
+            stackable.per_stack.stacked(stackable.stacks),
+            stackable.order,
+        )?;
+        inner.counters.push(StackCounter {
+            key: handle.key(),
+            stackable,
+            elapsed: 0.0,
+        });
+        inner.calculate_internal_value();
+        Ok(handle)
+    }
+
+    /// The stack count of a stackable modifier, None if there is no stackable modifier with this key
+    /// panics if refcell is borrowed
+    pub fn stacks(&self, key: ModifierKey) -> Option<u32> {
+        let mut inner = borrow_cell(&self.inner).unwrap();
+        inner.update_modifiers();
+        inner
+            .counter_mut(key)
+            .map(|counter| counter.stackable.stacks)
+    }
+
+    /// Adds stacks up to the maximum, returns the new count.
+    /// None if there is no stackable modifier with this key
+    /// panics if refcell is borrowed, see [`Stat::try_add_stacks()`]
+    pub fn add_stacks(&mut self, key: ModifierKey, stacks: u32) -> Option<u32> {
+        self.try_add_stacks(key, stacks).unwrap()
+    }
+
+    /// Same as [`Stat::add_stacks()`], but returns an error instead of panicking
+    pub fn try_add_stacks(
+        &mut self,
+        key: ModifierKey,
+        stacks: u32,
+    ) -> Result<Option<u32>, StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        Ok(inner.change_stacks(key, |count| count.saturating_add(stacks)))
+    }
+
+    /// Removes stacks down to 0, returns the new count. The modifier stays, it can gain stacks again.
+    /// None if there is no stackable modifier with this key
+    /// panics if refcell is borrowed, see [`Stat::try_remove_stacks()`]
+    pub fn remove_stacks(&mut self, key: ModifierKey, stacks: u32) -> Option<u32> {
+        self.try_remove_stacks(key, stacks).unwrap()
+    }
+
+    /// Same as [`Stat::remove_stacks()`], but returns an error instead of panicking
+    pub fn try_remove_stacks(
+        &mut self,
+        key: ModifierKey,
+        stacks: u32,
+    ) -> Result<Option<u32>, StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        Ok(inner.change_stacks(key, |count| count.saturating_sub(stacks)))
+    }
+}
+
+impl<const M: usize> StatInner<M> {
+    fn counter_mut(&mut self, key: ModifierKey) -> Option<&mut StackCounter> {
+        // the modifier might have been removed (or its handle dropped)
+        if !self.modifiers.iter().any(|m| m.key == key) {
+            return None;
+        }
+        self.counters.iter_mut().find(|counter| counter.key == key)
+    }
+
+    fn change_stacks(&mut self, key: ModifierKey, change: impl FnOnce(u32) -> u32) -> Option<u32> {
+        self.update_modifiers();
+        let counter = self.counter_mut(key)?;
+        let old = counter.stackable.stacks;
+        let wanted = change(old);
+        // a hit at the maximum still restarts the decay
+        if wanted > old {
+            counter.elapsed = 0.0;
+        }
+        let new = counter.stackable.clamp(wanted);
+        if new != old {
+            counter.stackable.stacks = new;
+            let modifier = counter.stackable.per_stack.stacked(new);
+            if let Some(meta) = self.modifiers.iter_mut().find(|m| m.key == key) {
+                meta.modifier = modifier;
+            }
+            self.calculate_internal_value();
+        }
+        Some(new)
+    }
+
+    // the value is not updated, returns true if a stack was lost
+    pub(crate) fn decay_stacks(&mut self, seconds: f32) -> bool {
+        let mut decayed = false;
+        for counter in self.counters.iter_mut() {
+            let Some(decay) = counter.stackable.decay else {
+                continue;
+            };
+            if counter.stackable.stacks == 0 {
+                continue;
+            }
+            counter.elapsed += seconds;
+            let old = counter.stackable.stacks;
+            while counter.elapsed >= decay && counter.stackable.stacks > 0 {
+                counter.elapsed -= decay;
+                counter.stackable.stacks -= 1;
+            }
+            if counter.stackable.stacks == 0 {
+                counter.elapsed = 0.0;
+            }
+            if counter.stackable.stacks != old {
+                let modifier = counter
+                    .stackable
+                    .per_stack
+                    .stacked(counter.stackable.stacks);
+                if let Some(meta) = self.modifiers.iter_mut().find(|m| m.key == counter.key) {
+                    meta.modifier = modifier;
+                }
+                decayed = true;
+            }
+        }
+        decayed
+    }
+
+    // the value is not updated, call calculate_internal_value() when done
+    fn push_stacking(&mut self, stacking: StackingModifier) -> Result<Stacked, StatError> {
+        self.update_modifiers();
+        self.prune_stacks();
+        let Some(index) = self.stacks.iter().position(|group| group.id == stacking.id) else {
+            let key = self.push_timed_modifier(&stacking)?;
+            self.stacks.push(StackGroup {
+                id: stacking.id,
+                policy: stacking.policy,
+                keys: vec![key],
+            });
+            return Ok(Stacked::Added(key));
+        };
+        self.stacks[index].policy = stacking.policy;
+        // make room first, so a strict stat at its capacity can still replace a modifier
+        let replaced = match stacking.policy {
+            StackingPolicy::Unique => return Ok(Stacked::Ignored),
+            StackingPolicy::Refresh => {
+                for meta in self.modifiers.iter_mut() {
+                    if self.stacks[index].keys.contains(&meta.key) {
+                        meta.remaining = stacking.duration;
+                    }
+                }
+                let newest = *self.stacks[index].keys.last().unwrap();
+                return Ok(Stacked::Refreshed(newest));
+            }
+            StackingPolicy::Stack(cap) => {
+                let count = self.stacks[index].keys.len();
+                let excess = (count + 1).saturating_sub(cap.max(1) as usize);
+                self.stacks[index].keys.drain(..excess).collect()
+            }
+            StackingPolicy::Strongest => Vec::new(),
+            StackingPolicy::Newest => core::mem::take(&mut self.stacks[index].keys),
+        };
+        for key in replaced {
+            self.remove_modifier(key);
+        }
+        let key = self.push_timed_modifier(&stacking)?;
+        self.stacks[index].keys.push(key);
+        Ok(Stacked::Added(key))
+    }
+
+    fn push_timed_modifier(
+        &mut self,
+        stacking: &StackingModifier,
+    ) -> Result<ModifierKey, StatError> {
+        let key = self.push_keyed_modifier(stacking.modifier, stacking.order)?;
+        // pushed last, calculate_internal_value() hasn't sorted it yet
+        if let Some(meta) = self.modifiers.last_mut() {
+            meta.remaining = stacking.duration;
+        }
+        Ok(key)
+    }
+
+    // forget modifiers that were removed or ran out
+    fn prune_stacks(&mut self) {
+        let modifiers = &self.modifiers;
+        self.counters
+            .retain(|counter| modifiers.iter().any(|m| m.key == counter.key));
+        self.stacks.retain_mut(|group| {
+            group
+                .keys
+                .retain(|key| modifiers.iter().any(|m| m.key == *key));
+            !group.keys.is_empty()
+        });
+    }
+
+    // marks the modifiers StackingPolicy::Strongest doesn't apply, called before the value is calculated
+    pub(crate) fn select_stacks(&mut self) {
+        self.prune_stacks();
+        if self.stacks.is_empty() {
+            return;
+        }
+        for meta in self.modifiers.iter_mut() {
+            meta.suppressed = false;
+        }
+        for group in self
+            .stacks
+            .iter()
+            .filter(|group| group.policy == StackingPolicy::Strongest && group.keys.len() > 1)
+        {
+            let strongest = self
+                .modifiers
+                .iter()
+                .filter(|m| group.keys.contains(&m.key))
+                // the newest one wins a tie
+                .max_by(|a, b| {
+                    strength(&a.modifier)
+                        .total_cmp(&strength(&b.modifier))
+                        .then(a.key.cmp(&b.key))
+                })
+                .map(|m| m.key);
+            for meta in self.modifiers.iter_mut() {
+                meta.suppressed |= group.keys.contains(&meta.key) && Some(meta.key) != strongest;
+            }
+        }
+    }
+}
@@ -1,81 +1,235 @@
-use crate::modifier::StatModifier;
+use crate::aggregation::Aggregation;
+use crate::cell::{
+    borrow_cell, new_interior_cell, read_cell, DropCounter, InteriorCell, ReferenceCounted, Weak,
+};
+use crate::derived::{DerivedBase, StatSource};
+use crate::error::StatError;
+use crate::growth::LevelGrowth;
+use crate::modifier::{ModifierTier, StatModifier};
+use crate::scaled::ScaledModifier;
+use crate::stacking::{StackCounter, StackGroup};
+use alloc::vec::Vec;
 use tinyvec::{ArrayVec, TinyVec};
 
-// By default (single-threaded) implementation is most optimized by using std::rc
-// if one needs Stat to live in a multithreaded environment, enabling sync feature uses std::sync instead
-#[cfg(not(feature = "sync"))]
-type ReferenceCounted<T> = std::rc::Rc<T>;
-#[cfg(not(feature = "sync"))]
-type Weak<T> = std::rc::Weak<T>;
-#[cfg(not(feature = "sync"))]
-type InteriorCell<T> = std::cell::RefCell<T>;
-#[cfg(feature = "sync")]
-type ReferenceCounted<T> = std::sync::Arc<T>;
-#[cfg(feature = "sync")]
-type Weak<T> = std::sync::Weak<T>;
-#[cfg(feature = "sync")]
-type InteriorCell<T> = std::sync::Arc<std::sync::Mutex<T>>;
-
-#[cfg(not(feature = "sync"))]
-#[inline]
-fn new_interior_cell<T>(value: T) -> InteriorCell<T> {
-    InteriorCell::new(value)
-}
+/// This handle is returned from calling ```stat.add_modifier()``` (technically it's returned in the Ok, result).
+///
+/// The handle controls the validity of a modifier.
+/// Once dropped, the modifier is automatically removed from the [`super::Stat`] that created it.
+pub type StatModifierHandle = ReferenceCounted<StatModifierHandleTag>;
 
-#[cfg(feature = "sync")]
-#[inline]
-fn new_interior_cell<T>(value: T) -> InteriorCell<T> {
-    std::sync::Arc::new(std::sync::Mutex::new(value))
+/// Lives inside a [`StatModifierHandle`], when the last handle is dropped it tells the [`super::Stat`] that created it.
+/// That way a stat only has to look through its modifiers after something was actually dropped.
+#[derive(Debug)]
+pub struct StatModifierHandleTag {
+    drop_counter: ReferenceCounted<DropCounter>,
+    key: ModifierKey,
 }
 
-#[cfg(not(feature = "sync"))]
-type RefMut<'a, T> = std::cell::RefMut<'a, T>;
+impl StatModifierHandleTag {
+    /// The key of the modifier this handle owns, use it with [`super::Stat::remove_modifier()`]
+    /// to remove the modifier while the handle is still alive, or store it somewhere a handle can't go.
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// let mut stat: Stat<2> = Stat::new(10.0);
+    /// let handle = stat.add_modifier(StatModifier::Flat(5.0));
+    /// assert!(stat.remove_modifier(handle.key()));
+    /// assert_eq!(stat.value(), 10.0);
+    /// ```
+    pub fn key(&self) -> ModifierKey {
+        self.key
+    }
+}
 
-#[cfg(feature = "sync")]
-type RefMut<'a, T> = std::sync::MutexGuard<'a, T>;
+impl Drop for StatModifierHandleTag {
+    fn drop(&mut self) {
+        self.drop_counter.increment();
+    }
+}
 
-#[cfg(not(feature = "sync"))]
-#[inline]
-fn borrow_cell<T>(cell: &InteriorCell<T>) -> std::cell::RefMut<T> {
-    cell.borrow_mut()
+/// A value that can be modified through [`super::StatModifier`]
+///
+/// ```const M: usize``` decides how many modifiers a stat can maximally hold (modifier are internally an array on the stack)
+///
+/// Cloning a stat gives you an independent copy (same as [`Stat::fork()`]), use [`Stat::share()`] for a linked view.
+/// This works the same with and without the sync feature.
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "StatData", into = "StatData")
+)]
+pub struct Stat<const M: usize> {
+    // base_value, modifiers and the calculated value live behind one cell, so a shared stat sees all of it
+    pub(crate) inner: ReferenceCounted<InteriorCell<StatInner<M>>>,
 }
 
-#[cfg(feature = "sync")]
-#[inline]
-fn borrow_cell<T>(cell: &InteriorCell<T>) -> std::sync::MutexGuard<T> {
-    // cell.try_lock().unwrap()
-    cell.lock().unwrap()
+#[derive(Clone, Debug)]
+pub(crate) struct StatInner<const M: usize> {
+    pub(crate) base_value: f32,
+    // calculated from base_value and modifiers
+    value: f32,
+    // base_value with only the base tier modifiers applied
+    base_total: f32,
+    pub(crate) modifiers: TinyVec<[ModifierMeta; M]>,
+    capacity_policy: CapacityPolicy,
+    // how many times the modifiers moved to the heap, for profiling
+    heap_spills: u32,
+    // handles created by this stat increment this counter when dropped
+    drop_listener: DropListener,
+    // a fork or restored snapshot can hold modifiers from another stat, we listen to their handles as well
+    foreign_drop_listeners: Vec<DropListener>,
+    // keys are never reused, a stale key can't remove a newer modifier.
+    // A restore rolls it back, so replaying the same inputs hands out the same keys
+    next_key: u64,
+    // Some for a DerivedStat, the base value comes from a formula
+    pub(crate) derived: Option<DerivedBase>,
+    // Some if the base value grows with the level
+    pub(crate) growth: Option<LevelGrowth>,
+    // modifiers whose magnitude comes from another stat, the modifier itself lives in modifiers
+    pub(crate) scaled: Vec<ScaledModifier>,
+    // modifiers applied with a StackingPolicy, grouped by their id
+    pub(crate) stacks: Vec<StackGroup>,
+    // stack counts of modifiers added with Stat::add_stackable_modifier()
+    pub(crate) counters: Vec<StackCounter>,
+    // order groups that don't apply their modifiers one after another, sorted by order
+    pub(crate) aggregations: Vec<(i32, Aggregation)>,
+    // incremented every time the value is calculated, a derived stat compares it to see if an input changed
+    version: u64,
 }
 
-/// This handle is returned from calling ```stat.add_modifier()``` (technically it's returned in the Ok, result).
+/// A plain id for a modifier, returned from [`Stat::insert_modifier()`].
 ///
-/// The handle controls the validity of a modifier.
-/// Once dropped, the modifier is automatically removed from the [`super::Stat`] that created it.
-pub type StatModifierHandle = ReferenceCounted<StatModifierHandleTag>;
-
-/// Just an empty 'flavor' struct, to indicate that the [`StatModifierHandle`] is an owner of some value
-#[derive(Copy, Clone, Debug, Default)]
+/// Unlike a [`StatModifierHandle`] a key doesn't own anything, the modifier stays until it's removed
+/// with [`Stat::remove_modifier()`]. Keys are [`Copy`] and serializable, which makes them easy to store in ECS components.
+/// A key is only meaningful for the stat that created it (and its forks).
+/// Keys are never reused, except after [`Stat::restore()`]: for deterministic rollback the keys handed out
+/// after the snapshot are handed out again, so keys taken after a snapshot are invalid once it's restored.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-pub struct StatModifierHandleTag;
+pub struct ModifierKey(u64);
 
-/// A value that can be modified through [`super::StatModifier`]
-///
-/// ```const M: usize``` decides how many modifiers a stat can maximally hold (modifier are internally an array on the stack)
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
+struct DropListener {
+    counter: ReferenceCounted<DropCounter>,
+    seen: u32,
+}
+
+impl DropListener {
+    #[inline]
+    fn any_dropped(&self) -> bool {
+        self.counter.get() != self.seen
+    }
+
+    fn is_same(&self, other: &Self) -> bool {
+        ReferenceCounted::ptr_eq(&self.counter, &other.counter)
+    }
+}
+
+/// Decides what happens when a [`Stat<M>`] gets more than M modifiers
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-pub struct Stat<const M: usize> {
-    pub base_value: f32,
-    // calculated from base_value and modifiers
-    #[cfg_attr(feature = "serde", serde(skip, default = "default_value"))]
-    value: InteriorCell<f32>,
+pub enum CapacityPolicy {
+    /// The modifiers are moved to the heap, the stat can hold any amount of modifiers
+    #[default]
+    Spill,
+    /// Never allocate, adding more than M modifiers fails with [`StatError::CapacityExceeded`]
+    Strict,
+}
 
-    #[cfg_attr(feature = "serde", serde(skip))]
-    modifiers: InteriorCell<TinyVec<[ModifierMeta; M]>>,
+// modifiers owned by handles can't be serialized, only the ones inserted with a ModifierKey are
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StatData {
+    base_value: f32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    modifiers: Vec<KeyedModifierData>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    next_key: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    stacks: Vec<StackGroup>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    aggregations: Vec<(i32, Aggregation)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    growth: Option<LevelGrowth>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct KeyedModifierData {
+    key: ModifierKey,
+    modifier: StatModifier,
+    order: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    remaining: Option<f32>,
+    #[serde(default, skip_serializing_if = "is_bonus")]
+    tier: ModifierTier,
 }
 
 #[cfg(feature = "serde")]
-fn default_value() -> InteriorCell<f32> {
-    new_interior_cell(0.0f32)
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+#[cfg(feature = "serde")]
+fn is_bonus(tier: &ModifierTier) -> bool {
+    *tier == ModifierTier::Bonus
+}
+
+#[cfg(feature = "serde")]
+impl<const M: usize> From<StatData> for Stat<M> {
+    fn from(data: StatData) -> Self {
+        let stat = Self::new(data.base_value);
+        {
+            let mut inner = borrow_cell(&stat.inner).unwrap();
+            inner.next_key = data.next_key;
+            for keyed in data.modifiers {
+                inner.modifiers.push(ModifierMeta {
+                    modifier: keyed.modifier,
+                    order: keyed.order,
+                    key: keyed.key,
+                    owner_modifier_weak: None,
+                    remaining: keyed.remaining,
+                    suppressed: false,
+                    tier: keyed.tier,
+                });
+            }
+            inner.stacks = data.stacks;
+            inner.aggregations = data.aggregations;
+            if let Some(growth) = data.growth {
+                inner.base_value = growth.base_value();
+                inner.growth = Some(growth);
+            }
+            inner.calculate_internal_value();
+        }
+        stat
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<const M: usize> From<Stat<M>> for StatData {
+    fn from(stat: Stat<M>) -> Self {
+        let mut inner = borrow_cell(&stat.inner).unwrap();
+        inner.update_modifiers();
+        Self {
+            base_value: inner.base_value,
+            modifiers: inner
+                .modifiers
+                .iter()
+                .filter(|m| m.owner_modifier_weak.is_none())
+                .map(|m| KeyedModifierData {
+                    key: m.key,
+                    modifier: m.modifier,
+                    order: m.order,
+                    remaining: m.remaining,
+                    tier: m.tier,
+                })
+                .collect(),
+            next_key: inner.next_key,
+            stacks: inner.stacks.clone(),
+            aggregations: inner.aggregations.clone(),
+            growth: inner.growth.clone(),
+        }
+    }
 }
 
 /// create a stat from i32 (Stat is always internally a f32)
@@ -92,21 +246,49 @@ impl<const M: usize> From<f32> for Stat<M> {
     }
 }
 
+/// A frozen copy of a [`Stat`] created by [`Stat::snapshot()`], hand it back to [`Stat::restore()`] to roll the stat back.
+///
+/// Modifiers are stored without owning them, a restored modifier is only active if its [`StatModifierHandle`] is still alive.
+/// For rollback netcode that means: keep (clone) the handles in your own saved game state, and the stat will follow along.
+/// Taking a snapshot doesn't allocate unless the stat has already spilled its modifiers to the heap.
 #[derive(Clone, Debug)]
-struct ModifierMeta {
-    modifier: StatModifier,
-    order: i32,
-    owner_modifier_weak: Weak<StatModifierHandleTag>,
+pub struct StatSnapshot<const M: usize> {
+    base_value: f32,
+    modifiers: TinyVec<[ModifierMeta; M]>,
+    drop_listener: DropListener,
+    foreign_drop_listeners: Vec<DropListener>,
+    next_key: u64,
+    scaled: Vec<ScaledModifier>,
+    stacks: Vec<StackGroup>,
+    counters: Vec<StackCounter>,
+    aggregations: Vec<(i32, Aggregation)>,
+    level: Option<u32>,
 }
 
-impl Default for ModifierMeta {
-    fn default() -> Self {
-        Self {
-            modifier: StatModifier::default(),
-            order: 0,
-            owner_modifier_weak: Weak::default(),
-        }
+impl<const M: usize> StatSnapshot<M> {
+    /// The base_value the stat had when the snapshot was taken
+    pub fn base_value(&self) -> f32 {
+        self.base_value
     }
+
+    /// How many modifiers were active when the snapshot was taken
+    pub fn modifier_count(&self) -> usize {
+        self.modifiers.len()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ModifierMeta {
+    pub(crate) modifier: StatModifier,
+    pub(crate) order: i32,
+    pub(crate) key: ModifierKey,
+    // None if the modifier was inserted with a key, it lives until it's removed
+    owner_modifier_weak: Option<Weak<StatModifierHandleTag>>,
+    // seconds left, removed by Stat::tick() when it runs out
+    pub(crate) remaining: Option<f32>,
+    // kept, but not applied because of its StackingPolicy
+    pub(crate) suppressed: bool,
+    tier: ModifierTier,
 }
 
 impl<const M: usize> Default for Stat<M> {
@@ -115,6 +297,13 @@ impl<const M: usize> Default for Stat<M> {
     }
 }
 
+/// Same as [`Stat::fork()`]
+impl<const M: usize> Clone for Stat<M> {
+    fn clone(&self) -> Self {
+        self.fork()
+    }
+}
+
 impl<const M: usize> Stat<M> {
     /// ```
     /// // EXAMPLE: Creates a stat that can hold a maximum of 3 modifiers
@@ -123,167 +312,870 @@ impl<const M: usize> Stat<M> {
     /// let attack_stat = Stat::<3>::new(0.0);
     /// ```
     pub fn new(base_value: f32) -> Self {
+        Self::with_capacity_policy(base_value, CapacityPolicy::Spill)
+    }
+
+    /// Creates a stat that never moves its modifiers to the heap, see [`CapacityPolicy::Strict`]
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// let mut stat: Stat<1> = Stat::new_strict(0.0);
+    /// let _handle = stat.add_modifier(StatModifier::Flat(1.0));
+    /// assert_eq!(
+    ///     stat.try_add_modifier(StatModifier::Flat(1.0)).unwrap_err(),
+    ///     StatError::CapacityExceeded { capacity: 1 }
+    /// );
+    /// ```
+    pub fn new_strict(base_value: f32) -> Self {
+        Self::with_capacity_policy(base_value, CapacityPolicy::Strict)
+    }
+
+    /// Creates a stat with the given [`CapacityPolicy`]
+    pub fn with_capacity_policy(base_value: f32, capacity_policy: CapacityPolicy) -> Self {
         let modifiers = TinyVec::Inline(ArrayVec::<[ModifierMeta; M]>::default());
-        Self {
+        Self::from_inner(StatInner {
             base_value,
-            value: new_interior_cell(base_value),
-            modifiers: new_interior_cell(modifiers),
+            value: base_value,
+            base_total: base_value,
+            modifiers,
+            capacity_policy,
+            heap_spills: 0,
+            drop_listener: DropListener::default(),
+            foreign_drop_listeners: Vec::new(),
+            next_key: 0,
+            derived: None,
+            growth: None,
+            scaled: Vec::new(),
+            stacks: Vec::new(),
+            counters: Vec::new(),
+            aggregations: Vec::new(),
+            version: 0,
+        })
+    }
+
+    fn from_inner(inner: StatInner<M>) -> Self {
+        Self {
+            inner: ReferenceCounted::new(new_interior_cell(inner)),
         }
     }
 
-    /// Add a modifier using the default order. [`super::StatModifier::default_order()`]
-    /// panics if refcell is borrowed
-    pub fn add_modifier(&mut self, modifier: StatModifier) -> StatModifierHandle {
-        // We have to update the modifiers array in case one has been dropped.
-        // The modifier array could be full of data, yet have modifiers that aren't valid.
-        // If we drop a modifier and then add one right away, there should be space for it to be added.
-        // This ensures the array is up to date.
-        self.update_modifiers();
+    /// Creates an independent copy, with a copy of all modifiers.
+    /// The copied modifiers are still controlled by the original handles, but adding modifiers
+    /// or changing the base value of one stat does not affect the other.
+    /// panics if refcell is borrowed, see [`Stat::try_fork()`]
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// let mut stat: Stat<2> = Stat::new(10.0);
+    /// let mut fork = stat.fork();
+    /// let _handle = fork.add_modifier(StatModifier::Flat(5.0));
+    /// assert_eq!(stat.value(), 10.0);
+    /// assert_eq!(fork.value(), 15.0);
+    /// ```
+    pub fn fork(&self) -> Self {
+        self.try_fork().unwrap()
+    }
 
-        let handle = ReferenceCounted::new(StatModifierHandleTag);
-        let meta = ModifierMeta {
-            order: modifier.default_order(),
-            modifier,
-            owner_modifier_weak: ReferenceCounted::downgrade(&handle),
-        };
+    /// Same as [`Stat::fork()`], but returns an error instead of panicking
+    pub fn try_fork(&self) -> Result<Self, StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        inner.update_modifiers();
+        Ok(Self::from_inner(inner.clone()))
+    }
 
-        let mut modifiers = borrow_cell(&self.modifiers);
-        if modifiers.len() + 1 > modifiers.capacity() {
-            modifiers.move_to_the_heap();
+    /// Creates a linked view of this stat, both stats share base value and modifiers.
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// let mut stat: Stat<2> = Stat::new(10.0);
+    /// let view = stat.share();
+    /// let _handle = stat.add_modifier(StatModifier::Flat(5.0));
+    /// assert_eq!(view.value(), 15.0);
+    /// ```
+    pub fn share(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
         }
-        modifiers.push(meta);
-        drop(modifiers);
+    }
 
-        self.calculate_internal_value();
-        handle
+    /// Returns true if both stats are linked views created through [`Stat::share()`]
+    pub fn is_shared_with(&self, other: &Self) -> bool {
+        ReferenceCounted::ptr_eq(&self.inner, &other.inner)
+    }
+
+    /// Returns the base value, without any modifiers applied
+    /// panics if refcell is borrowed, see [`Stat::try_base_value()`]
+    pub fn base_value(&self) -> f32 {
+        self.try_base_value().unwrap()
+    }
+
+    /// Same as [`Stat::base_value()`], but returns an error instead of panicking
+    pub fn try_base_value(&self) -> Result<f32, StatError> {
+        {
+            let inner = read_cell(&self.inner)?;
+            if inner.derived.is_none() {
+                return Ok(inner.base_value);
+            }
+        }
+        let mut inner = borrow_cell(&self.inner)?;
+        inner.update_sources()?;
+        Ok(inner.base_value)
     }
 
+    /// Changes the base value, the modifiers are applied to the new base value right away
+    /// panics if refcell is borrowed, see [`Stat::try_set_base_value()`]
+    pub fn set_base_value(&mut self, base_value: f32) {
+        self.try_set_base_value(base_value).unwrap()
+    }
+
+    /// Same as [`Stat::set_base_value()`], but returns an error instead of panicking
+    pub fn try_set_base_value(&mut self, base_value: f32) -> Result<(), StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        inner.base_value = base_value;
+        inner.update_modifiers();
+        inner.calculate_internal_value();
+        Ok(())
+    }
+
+    /// Returns the [`CapacityPolicy`] of this stat
     /// panics if refcell is borrowed
+    pub fn capacity_policy(&self) -> CapacityPolicy {
+        read_cell(&self.inner).unwrap().capacity_policy
+    }
+
+    /// Changes the [`CapacityPolicy`], modifiers that already live on the heap stay there
+    /// panics if refcell is borrowed
+    pub fn set_capacity_policy(&mut self, capacity_policy: CapacityPolicy) {
+        borrow_cell(&self.inner).unwrap().capacity_policy = capacity_policy;
+    }
+
+    /// Returns how many times the modifiers had to be moved to the heap, useful for picking a better M
+    /// panics if refcell is borrowed
+    pub fn heap_spills(&self) -> u32 {
+        read_cell(&self.inner).unwrap().heap_spills
+    }
+
+    /// Add a modifier using the default order. [`super::StatModifier::default_order()`]
+    /// panics if refcell is borrowed or the capacity is exceeded in strict mode, see [`Stat::try_add_modifier()`]
+    pub fn add_modifier(&mut self, modifier: StatModifier) -> StatModifierHandle {
+        self.try_add_modifier(modifier).unwrap()
+    }
+
+    /// Same as [`Stat::add_modifier()`], but returns an error instead of panicking
+    pub fn try_add_modifier(
+        &mut self,
+        modifier: StatModifier,
+    ) -> Result<StatModifierHandle, StatError> {
+        self.try_add_modifier_with_order(modifier, modifier.default_order())
+    }
+
+    /// panics if refcell is borrowed or the capacity is exceeded in strict mode, see [`Stat::try_add_modifier_with_order()`]
     pub fn add_modifier_with_order(
         &mut self,
         modifier: StatModifier,
         order: i32,
     ) -> StatModifierHandle {
-        // We have to update the modifiers array in case one has been dropped.
-        // The modifier array could be full of data, yet have modifiers that aren't valid.
-        // If we drop a modifier and then add one right away, there should be space for it to be added.
-        // This ensures the array is up to date.
-        self.update_modifiers();
-        let handle = ReferenceCounted::new(StatModifierHandleTag);
-        let modifier_meta = ModifierMeta {
-            modifier,
-            owner_modifier_weak: ReferenceCounted::downgrade(&handle),
-            order,
-        };
+        self.try_add_modifier_with_order(modifier, order).unwrap()
+    }
+
+    /// Same as [`Stat::add_modifier_with_order()`], but returns an error instead of panicking
+    pub fn try_add_modifier_with_order(
+        &mut self,
+        modifier: StatModifier,
+        order: i32,
+    ) -> Result<StatModifierHandle, StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        let handle = inner.push_handle_modifier(modifier, order)?;
+        // value needs to update
+        inner.calculate_internal_value();
+        Ok(handle)
+    }
+
+    /// Add a modifier that lives until it's removed with [`Stat::remove_modifier()`], using the default order.
+    /// panics if refcell is borrowed or the capacity is exceeded in strict mode, see [`Stat::try_insert_modifier()`]
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// let mut stat: Stat<2> = Stat::new(10.0);
+    /// let key = stat.insert_modifier(StatModifier::Flat(5.0));
+    /// assert_eq!(stat.value(), 15.0);
+    /// assert!(stat.remove_modifier(key));
+    /// assert_eq!(stat.value(), 10.0);
+    /// ```
+    pub fn insert_modifier(&mut self, modifier: StatModifier) -> ModifierKey {
+        self.try_insert_modifier(modifier).unwrap()
+    }
+
+    /// Same as [`Stat::insert_modifier()`], but returns an error instead of panicking
+    pub fn try_insert_modifier(
+        &mut self,
+        modifier: StatModifier,
+    ) -> Result<ModifierKey, StatError> {
+        self.try_insert_modifier_with_order(modifier, modifier.default_order())
+    }
 
-        let mut modifiers = borrow_cell(&self.modifiers);
-        if modifiers.len() + 1 > modifiers.capacity() {
-            modifiers.move_to_the_heap();
+    /// panics if refcell is borrowed or the capacity is exceeded in strict mode, see [`Stat::try_insert_modifier_with_order()`]
+    pub fn insert_modifier_with_order(
+        &mut self,
+        modifier: StatModifier,
+        order: i32,
+    ) -> ModifierKey {
+        self.try_insert_modifier_with_order(modifier, order)
+            .unwrap()
+    }
+
+    /// Same as [`Stat::insert_modifier_with_order()`], but returns an error instead of panicking
+    pub fn try_insert_modifier_with_order(
+        &mut self,
+        modifier: StatModifier,
+        order: i32,
+    ) -> Result<ModifierKey, StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        let key = inner.push_keyed_modifier(modifier, order)?;
+        inner.calculate_internal_value();
+        Ok(key)
+    }
+
+    /// Removes the modifier, returns false if there is no modifier with this key.
+    /// Works for modifiers owned by a handle too (see [`StatModifierHandleTag::key()`]), the handle just stops doing anything.
+    /// panics if refcell is borrowed, see [`Stat::try_remove_modifier()`]
+    pub fn remove_modifier(&mut self, key: ModifierKey) -> bool {
+        self.try_remove_modifier(key).unwrap()
+    }
+
+    /// Same as [`Stat::remove_modifier()`], but returns an error instead of panicking
+    pub fn try_remove_modifier(&mut self, key: ModifierKey) -> Result<bool, StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        inner.update_modifiers();
+        let removed = inner.remove_modifier(key);
+        if removed {
+            inner.calculate_internal_value();
         }
-        modifiers.push(modifier_meta);
-        drop(modifiers);
+        Ok(removed)
+    }
 
-        // value needs to update
-        self.calculate_internal_value();
-        handle
+    /// Turns a handle owned modifier into a keyed one, the modifier stays after the handle is dropped,
+    /// until it's removed with [`Stat::remove_modifier()`]. Returns None if the handle doesn't belong to this stat
+    /// panics if refcell is borrowed, see [`Stat::try_detach_modifier()`]
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// let mut stat: Stat<2> = Stat::new(10.0);
+    /// let handle = stat.add_modifier(StatModifier::Flat(5.0));
+    /// let key = stat.detach_modifier(handle).unwrap();
+    /// assert_eq!(stat.value(), 15.0);
+    /// stat.remove_modifier(key);
+    /// assert_eq!(stat.value(), 10.0);
+    /// ```
+    pub fn detach_modifier(&mut self, handle: StatModifierHandle) -> Option<ModifierKey> {
+        self.try_detach_modifier(handle).unwrap()
     }
 
-    // check if any modifiers have been dropped, and update the value + array
+    /// Same as [`Stat::detach_modifier()`], but returns an error instead of panicking
+    pub fn try_detach_modifier(
+        &mut self,
+        handle: StatModifierHandle,
+    ) -> Result<Option<ModifierKey>, StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        let owner = ReferenceCounted::downgrade(&handle);
+        let meta = inner.modifiers.iter_mut().find(|m| {
+            m.owner_modifier_weak
+                .as_ref()
+                .is_some_and(|weak| weak.ptr_eq(&owner))
+        });
+        Ok(meta.map(|meta| {
+            meta.owner_modifier_weak = None;
+            meta.key
+        }))
+        // handle is dropped here, the modifier no longer cares
+    }
+
+    /// Counts down the duration of timed modifiers (see [`super::StackingModifier::with_duration()`]),
+    /// the ones that run out are removed. Stackable modifiers with decay lose their stacks.
+    /// panics if refcell is borrowed, see [`Stat::try_tick()`]
+    pub fn tick(&mut self, seconds: f32) {
+        self.try_tick(seconds).unwrap()
+    }
+
+    /// Same as [`Stat::tick()`], but returns an error instead of panicking
+    pub fn try_tick(&mut self, seconds: f32) -> Result<(), StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        inner.update_modifiers();
+        if inner.tick(seconds) {
+            inner.calculate_internal_value();
+        }
+        Ok(())
+    }
+
+    /// Seconds left before the modifier runs out, None if it isn't timed (or doesn't exist)
     /// panics if refcell is borrowed
-    fn update_modifiers(&self) {
-        let mut modifiers = borrow_cell(&self.modifiers);
-        let mut any_modifier_dropped = false;
+    pub fn remaining_duration(&self, key: ModifierKey) -> Option<f32> {
+        let mut inner = borrow_cell(&self.inner).unwrap();
+        inner.update_modifiers();
+        inner
+            .modifiers
+            .iter()
+            .find(|m| m.key == key)
+            .and_then(|m| m.remaining)
+    }
 
-        modifiers.retain(|m| {
-            let retain = m.owner_modifier_weak.upgrade().is_some();
-            if !retain {
-                any_modifier_dropped = true;
-            }
-            retain
-        });
-        drop(modifiers);
+    /// Adds a modifier to the base tier using the default order, see [`ModifierTier`]
+    /// panics if refcell is borrowed or the capacity is exceeded in strict mode, see [`Stat::try_add_base_modifier()`]
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// let mut strength: Stat<4> = Stat::new(40.0);
+    /// let _talent = strength.add_base_modifier(StatModifier::Flat(10.0));
+    /// let _sword = strength.add_modifier(StatModifier::Flat(23.0));
+    /// assert_eq!((strength.base_total(), strength.bonus()), (50.0, 23.0));
+    /// // +10% base strength leaves the sword alone
+    /// let _blessing = strength.add_base_modifier(StatModifier::PercentAdd(0.1));
+    /// assert_eq!((strength.base_total(), strength.bonus()), (55.0, 23.0));
+    /// ```
+    pub fn add_base_modifier(&mut self, modifier: StatModifier) -> StatModifierHandle {
+        self.try_add_base_modifier(modifier).unwrap()
+    }
 
-        if any_modifier_dropped {
-            self.calculate_internal_value();
+    /// Same as [`Stat::add_base_modifier()`], but returns an error instead of panicking
+    pub fn try_add_base_modifier(
+        &mut self,
+        modifier: StatModifier,
+    ) -> Result<StatModifierHandle, StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        let handle = inner.push_handle_modifier(modifier, modifier.default_order())?;
+        inner.set_modifier_tier(handle.key(), ModifierTier::Base);
+        inner.calculate_internal_value();
+        Ok(handle)
+    }
+
+    /// Same as [`Stat::insert_modifier()`], but the modifier is in the base tier
+    /// panics if refcell is borrowed or the capacity is exceeded in strict mode, see [`Stat::try_insert_base_modifier()`]
+    pub fn insert_base_modifier(&mut self, modifier: StatModifier) -> ModifierKey {
+        self.try_insert_base_modifier(modifier).unwrap()
+    }
+
+    /// Same as [`Stat::insert_base_modifier()`], but returns an error instead of panicking
+    pub fn try_insert_base_modifier(
+        &mut self,
+        modifier: StatModifier,
+    ) -> Result<ModifierKey, StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        let key = inner.push_keyed_modifier(modifier, modifier.default_order())?;
+        inner.set_modifier_tier(key, ModifierTier::Base);
+        inner.calculate_internal_value();
+        Ok(key)
+    }
+
+    /// Moves a modifier to another tier, works for every kind of modifier (stacking, scaled...).
+    /// Returns false if there is no modifier with this key
+    /// panics if refcell is borrowed, see [`Stat::try_set_modifier_tier()`]
+    pub fn set_modifier_tier(&mut self, key: ModifierKey, tier: ModifierTier) -> bool {
+        self.try_set_modifier_tier(key, tier).unwrap()
+    }
+
+    /// Same as [`Stat::set_modifier_tier()`], but returns an error instead of panicking
+    pub fn try_set_modifier_tier(
+        &mut self,
+        key: ModifierKey,
+        tier: ModifierTier,
+    ) -> Result<bool, StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        inner.update_modifiers();
+        let found = inner.set_modifier_tier(key, tier);
+        if found {
+            inner.calculate_internal_value();
         }
+        Ok(found)
+    }
+
+    /// Returns true if the stat has an active modifier with this key
+    /// panics if refcell is borrowed
+    pub fn contains_modifier(&self, key: ModifierKey) -> bool {
+        let mut inner = borrow_cell(&self.inner).unwrap();
+        inner.update_modifiers();
+        inner.modifiers.iter().any(|m| m.key == key)
     }
 
     /// returns base value with modifiers applied from self AND other stats's modifiers
     /// the other_stat's modifiers are all applied after 'self' applies it's modifiers
     /// the base value from other_stat is not taken into any account
-    /// panics if refcell is borrowed
+    /// panics if refcell is borrowed, see [`Stat::try_value_with_integrated_modifiers()`]
     pub fn value_with_integrated_modifiers(&mut self, other_stat: &Self) -> f32 {
-        other_stat.update_modifiers();
-        let highest_order = self.highest_order();
+        self.try_value_with_integrated_modifiers(other_stat)
+            .unwrap()
+    }
+
+    /// Same as [`Stat::value_with_integrated_modifiers()`], but returns an error instead of panicking
+    pub fn try_value_with_integrated_modifiers(
+        &mut self,
+        other_stat: &Self,
+    ) -> Result<f32, StatError> {
+        let highest_order = self.try_highest_order()?;
 
-        let mut other_modifiers = borrow_cell(&other_stat.modifiers);
-        let mut temporary_handles: TinyVec<[StatModifierHandle; M]> =
+        // copy the modifiers first, other_stat could be a shared view of self
+        let other_modifiers = {
+            let mut other_inner = borrow_cell(&other_stat.inner)?;
+            other_inner.update_modifiers();
+            other_inner.modifiers.clone()
+        };
+        // wrapped in Option, TinyVec needs Default
+        let mut temporary_handles: TinyVec<[Option<StatModifierHandle>; M]> =
             TinyVec::with_capacity(other_modifiers.len());
 
-        for modifier in other_modifiers.iter_mut() {
-            temporary_handles.push(self.add_modifier_with_order(
-                modifier.modifier.clone(),
+        for modifier in other_modifiers.iter().filter(|m| !m.suppressed) {
+            temporary_handles.push(Some(self.try_add_modifier_with_order(
+                modifier.modifier,
                 highest_order + 1 + modifier.order,
-            ));
+            )?));
         }
-        self.value()
+        self.try_value()
     }
 
     /// Returns the highest order of all modifiers
-    /// panics if refcell is borrowed
+    /// panics if refcell is borrowed, see [`Stat::try_highest_order()`]
     pub fn highest_order(&self) -> i32 {
-        self.update_modifiers();
-        let modifiers = borrow_cell(&self.modifiers);
-        modifiers
+        self.try_highest_order().unwrap()
+    }
+
+    /// Same as [`Stat::highest_order()`], but returns an error instead of panicking
+    pub fn try_highest_order(&self) -> Result<i32, StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        inner.update_modifiers();
+        Ok(inner
+            .modifiers
             .iter()
             .map(|modifier_meta| modifier_meta.order)
             .max()
-            .unwrap_or(0)
+            .unwrap_or(0))
+    }
+
+    /// Copies the base value, modifiers and their order, so the stat can be rolled back later with [`Stat::restore()`]
+    /// panics if refcell is borrowed, see [`Stat::try_snapshot()`]
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// let mut stat: Stat<2> = Stat::new(10.0);
+    /// let snapshot = stat.snapshot();
+    /// let _handle = stat.add_modifier(StatModifier::Flat(5.0));
+    /// assert_eq!(stat.value(), 15.0);
+    /// stat.restore(&snapshot);
+    /// assert_eq!(stat.value(), 10.0);
+    /// ```
+    pub fn snapshot(&self) -> StatSnapshot<M> {
+        self.try_snapshot().unwrap()
+    }
+
+    /// Same as [`Stat::snapshot()`], but returns an error instead of panicking
+    pub fn try_snapshot(&self) -> Result<StatSnapshot<M>, StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        inner.update_modifiers();
+        Ok(StatSnapshot {
+            base_value: inner.base_value,
+            modifiers: inner.modifiers.clone(),
+            drop_listener: inner.drop_listener.clone(),
+            foreign_drop_listeners: inner.foreign_drop_listeners.clone(),
+            next_key: inner.next_key,
+            scaled: inner.scaled.clone(),
+            stacks: inner.stacks.clone(),
+            counters: inner.counters.clone(),
+            aggregations: inner.aggregations.clone(),
+            level: inner.growth.as_ref().map(LevelGrowth::level),
+        })
+    }
+
+    /// Puts the stat back into the state it had when `snapshot` was taken.
+    /// Modifiers whose handle has been dropped since then stay removed.
+    /// Keys taken after the snapshot are invalid, they will be handed out again (see [`ModifierKey`])
+    /// panics if refcell is borrowed, or a scaled modifier of the snapshot (from another stat)
+    /// reads from this stat, see [`Stat::try_restore()`]
+    pub fn restore(&mut self, snapshot: &StatSnapshot<M>) {
+        self.try_restore(snapshot).unwrap()
+    }
+
+    /// Same as [`Stat::restore()`], but returns an error instead of panicking.
+    /// The stat is unchanged when an error is returned
+    pub fn try_restore(&mut self, snapshot: &StatSnapshot<M>) -> Result<(), StatError> {
+        for source in snapshot.scaled.iter().filter_map(ScaledModifier::source) {
+            self.check_cycle(&StatSource(source))?;
+        }
+        let mut inner = borrow_cell(&self.inner)?;
+        inner.base_value = snapshot.base_value;
+        inner.modifiers.clone_from(&snapshot.modifiers);
+        // rolled back as well, replaying the same inputs gives the same keys
+        inner.next_key = snapshot.next_key;
+        inner.scaled.clone_from(&snapshot.scaled);
+        inner.stacks.clone_from(&snapshot.stacks);
+        inner.counters.clone_from(&snapshot.counters);
+        inner.aggregations.clone_from(&snapshot.aggregations);
+        // the base value is restored as well, the growth only needs to know the level
+        if let (Some(growth), Some(level)) = (&mut inner.growth, snapshot.level) {
+            growth.set_level(level);
+        }
+        inner.invalidate_sources();
+        // the snapshot might come from another stat, make sure we hear about its handles being dropped
+        for listener in
+            core::iter::once(&snapshot.drop_listener).chain(&snapshot.foreign_drop_listeners)
+        {
+            if !inner.drop_listener.is_same(listener)
+                && !inner
+                    .foreign_drop_listeners
+                    .iter()
+                    .any(|l| l.is_same(listener))
+            {
+                inner.foreign_drop_listeners.push(listener.clone());
+            }
+        }
+        inner.update_modifiers();
+        inner.calculate_internal_value();
+        Ok(())
     }
 
     /// Returns the internal base_value with modifiers applied
-    /// panics if refcell is borrowed
+    /// panics if refcell is borrowed, see [`Stat::try_value()`]
     pub fn value(&self) -> f32 {
-        self.update_modifiers();
-        *borrow_cell(&self.value)
+        self.try_value().unwrap()
+    }
+
+    /// Same as [`Stat::value()`], but returns an error instead of panicking
+    /// ```
+    /// # use game_stat::prelude::*;
+    /// let stat: Stat<2> = Stat::new(10.0);
+    /// assert_eq!(stat.try_value(), Ok(10.0));
+    /// ```
+    pub fn try_value(&self) -> Result<f32, StatError> {
+        // most of the time nothing changed, so try with a read lock first (doesn't block other readers with rwlock)
+        {
+            let inner = read_cell(&self.inner)?;
+            if !inner.any_handle_dropped() && !inner.has_sources() {
+                return Ok(inner.value);
+            }
+        }
+        let mut inner = borrow_cell(&self.inner)?;
+        inner.update_modifiers();
+        inner.update_sources()?;
+        Ok(inner.value)
+    }
+
+    /// The base value with the base tier modifiers applied, the "50" in "Strength 50 (+23)"
+    /// panics if refcell is borrowed, see [`Stat::try_base_total()`]
+    pub fn base_total(&self) -> f32 {
+        self.try_base_total().unwrap()
+    }
+
+    /// Same as [`Stat::base_total()`], but returns an error instead of panicking
+    pub fn try_base_total(&self) -> Result<f32, StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        inner.update_modifiers();
+        inner.update_sources()?;
+        Ok(inner.base_total)
+    }
+
+    /// What the bonus tier modifiers add on top of [`Stat::base_total()`], the "+23" in "Strength 50 (+23)".
+    /// Negative if the bonus modifiers lower the value
+    /// panics if refcell is borrowed, see [`Stat::try_bonus()`]
+    pub fn bonus(&self) -> f32 {
+        self.try_bonus().unwrap()
+    }
+
+    /// Same as [`Stat::bonus()`], but returns an error instead of panicking
+    pub fn try_bonus(&self) -> Result<f32, StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        inner.update_modifiers();
+        inner.update_sources()?;
+        Ok(inner.value - inner.base_total)
+    }
+
+    // changes every time the value is calculated, even if it ends up the same
+    pub(crate) fn try_version(&self) -> Result<u64, StatError> {
+        let mut inner = borrow_cell(&self.inner)?;
+        inner.update_modifiers();
+        inner.update_sources()?;
+        Ok(inner.version)
     }
 
     /// Returns the INPUT base_value (ignores self) with modifiers applied
-    /// panics if refcell is borrowed
+    /// panics if refcell is borrowed, see [`Stat::try_value_with_base()`]
     pub fn value_with_base(&self, base_value: f32) -> f32 {
+        self.try_value_with_base(base_value).unwrap()
+    }
+
+    /// Same as [`Stat::value_with_base()`], but returns an error instead of panicking
+    pub fn try_value_with_base(&self, base_value: f32) -> Result<f32, StatError> {
         let mut value = base_value;
-        // Order the modifiers
-        let mut modifiers = borrow_cell(&self.modifiers);
-        Self::order_modifiers(&mut modifiers);
-        Self::apply_modifiers_to_value(modifiers, &mut value);
-        value
+        // the modifiers are always kept in order
+        let inner = read_cell(&self.inner)?;
+        if inner.any_handle_dropped() {
+            drop(inner);
+            let mut inner = borrow_cell(&self.inner)?;
+            inner.update_modifiers();
+            inner.apply_modifiers_to_value(&mut value);
+            return Ok(value);
+        }
+        inner.apply_modifiers_to_value(&mut value);
+        Ok(value)
+    }
+}
+
+impl<const M: usize> StatInner<M> {
+    fn new_key(&mut self) -> ModifierKey {
+        let key = ModifierKey(self.next_key);
+        self.next_key += 1;
+        key
+    }
+
+    pub(crate) fn value(&self) -> f32 {
+        self.value
+    }
+
+    pub(crate) fn version(&self) -> u64 {
+        self.version
+    }
+
+    // true if the value depends on other stats, it can change without touching this stat
+    #[inline]
+    fn has_sources(&self) -> bool {
+        self.derived.is_some() || !self.scaled.is_empty()
+    }
+
+    // everything is read again next time, even if the other stats didn't change
+    fn invalidate_sources(&mut self) {
+        if let Some(derived) = &mut self.derived {
+            derived.invalidate();
+        }
+        for scaled in self.scaled.iter_mut() {
+            scaled.invalidate();
+        }
+    }
+
+    // runs the formula of a derived stat and rescales the scaled modifiers, if the stats they read from changed
+    pub(crate) fn update_sources(&mut self) -> Result<(), StatError> {
+        let mut changed = false;
+        if let Some(derived) = &mut self.derived {
+            if let Some(base_value) = derived.update()? {
+                self.base_value = base_value;
+                changed = true;
+            }
+        }
+        if !self.scaled.is_empty() {
+            // the modifier might have been removed (or its handle dropped)
+            let modifiers = &mut self.modifiers;
+            self.scaled
+                .retain(|scaled| modifiers.iter().any(|m| m.key == scaled.key()));
+            for scaled in self.scaled.iter_mut() {
+                if let Some(modifier) = scaled.update()? {
+                    if let Some(meta) = modifiers.iter_mut().find(|m| m.key == scaled.key()) {
+                        meta.modifier = modifier;
+                    }
+                    changed = true;
+                }
+            }
+        }
+        if changed {
+            self.calculate_internal_value();
+        }
+        Ok(())
+    }
+
+    pub(crate) fn push_handle_modifier(
+        &mut self,
+        modifier: StatModifier,
+        order: i32,
+    ) -> Result<StatModifierHandle, StatError> {
+        let key = self.new_key();
+        let handle = ReferenceCounted::new(StatModifierHandleTag {
+            drop_counter: self.drop_listener.counter.clone(),
+            key,
+        });
+        self.push_modifier(
+            modifier,
+            order,
+            key,
+            Some(ReferenceCounted::downgrade(&handle)),
+        )?;
+        Ok(handle)
+    }
+
+    pub(crate) fn push_keyed_modifier(
+        &mut self,
+        modifier: StatModifier,
+        order: i32,
+    ) -> Result<ModifierKey, StatError> {
+        let key = self.new_key();
+        self.push_modifier(modifier, order, key, None)?;
+        Ok(key)
+    }
+
+    // a handle modifier that runs out after `duration` seconds (if set), the value is updated
+    #[cfg(feature = "serde")]
+    pub(crate) fn push_timed_handle_modifier(
+        &mut self,
+        modifier: StatModifier,
+        order: i32,
+        duration: Option<f32>,
+    ) -> Result<StatModifierHandle, StatError> {
+        let handle = self.push_handle_modifier(modifier, order)?;
+        // pushed last, calculate_internal_value() hasn't sorted it yet
+        if let Some(meta) = self.modifiers.last_mut() {
+            meta.remaining = duration;
+        }
+        self.calculate_internal_value();
+        Ok(handle)
+    }
+
+    // changes an existing modifier in place, returns false if there is no modifier with this key
+    #[cfg(feature = "serde")]
+    pub(crate) fn retune_modifier(
+        &mut self,
+        key: ModifierKey,
+        modifier: StatModifier,
+        order: i32,
+    ) -> bool {
+        self.update_modifiers();
+        let Some(meta) = self.modifiers.iter_mut().find(|m| m.key == key) else {
+            return false;
+        };
+        meta.modifier = modifier;
+        meta.order = order;
+        self.calculate_internal_value();
+        true
+    }
+
+    // the value is not updated, call calculate_internal_value() when done
+    fn set_modifier_tier(&mut self, key: ModifierKey, tier: ModifierTier) -> bool {
+        let Some(meta) = self.modifiers.iter_mut().find(|m| m.key == key) else {
+            return false;
+        };
+        meta.tier = tier;
+        true
+    }
+
+    // the value is not updated, call calculate_internal_value() when done
+    pub(crate) fn remove_modifier(&mut self, key: ModifierKey) -> bool {
+        let count = self.modifiers.len();
+        self.modifiers.retain(|m| m.key != key);
+        self.modifiers.len() != count
+    }
+
+    // the value is not updated, call calculate_internal_value() when done
+    fn push_modifier(
+        &mut self,
+        modifier: StatModifier,
+        order: i32,
+        key: ModifierKey,
+        owner_modifier_weak: Option<Weak<StatModifierHandleTag>>,
+    ) -> Result<(), StatError> {
+        // We have to update the modifiers array in case one has been dropped.
+        // The modifier array could be full of data, yet have modifiers that aren't valid.
+        // If we drop a modifier and then add one right away, there should be space for it to be added.
+        // This ensures the array is up to date.
+        self.update_modifiers();
+
+        if self.modifiers.len() + 1 > self.modifiers.capacity() {
+            match self.capacity_policy {
+                CapacityPolicy::Spill => {
+                    self.modifiers.move_to_the_heap();
+                    self.heap_spills += 1;
+                }
+                CapacityPolicy::Strict => return Err(StatError::CapacityExceeded { capacity: M }),
+            }
+        }
+        self.modifiers.push(ModifierMeta {
+            modifier,
+            order,
+            key,
+            owner_modifier_weak,
+            remaining: None,
+            suppressed: false,
+            tier: ModifierTier::Bonus,
+        });
+        Ok(())
+    }
+
+    // the value is not updated, returns true if a modifier ran out or lost a stack
+    fn tick(&mut self, seconds: f32) -> bool {
+        let decayed = self.decay_stacks(seconds);
+        let mut expired = false;
+        for remaining in self
+            .modifiers
+            .iter_mut()
+            .filter_map(|m| m.remaining.as_mut())
+        {
+            *remaining -= seconds;
+            expired |= *remaining <= 0.0;
+        }
+        if expired {
+            self.modifiers
+                .retain(|m| m.remaining.is_none_or(|remaining| remaining > 0.0));
+        }
+        decayed || expired
+    }
+
+    // cheap check, no need to look at the modifiers unless a handle has been dropped
+    #[inline]
+    fn any_handle_dropped(&self) -> bool {
+        self.drop_listener.any_dropped()
+            || self
+                .foreign_drop_listeners
+                .iter()
+                .any(DropListener::any_dropped)
+    }
+
+    // check if any modifiers have been dropped, and update the value + array
+    pub(crate) fn update_modifiers(&mut self) {
+        if !self.any_handle_dropped() {
+            return;
+        }
+        // read the counters before looking at the modifiers, a handle dropped while we're busy is picked up next time
+        self.drop_listener.seen = self.drop_listener.counter.get();
+        for listener in self.foreign_drop_listeners.iter_mut() {
+            listener.seen = listener.counter.get();
+        }
+
+        let mut any_modifier_dropped = false;
+        self.modifiers.retain(|m| {
+            let retain = match &m.owner_modifier_weak {
+                Some(owner) => owner.strong_count() > 0,
+                // inserted with a key, only removed explicitly
+                None => true,
+            };
+            if !retain {
+                any_modifier_dropped = true;
+            }
+            retain
+        });
+        // nobody else holds the counter, so no handle can increment it anymore
+        self.foreign_drop_listeners
+            .retain(|l| ReferenceCounted::strong_count(&l.counter) > 1);
+
+        if any_modifier_dropped {
+            self.calculate_internal_value();
+        }
     }
 
     /// order modifiers and apply to base value
-    /// panics if refcell is borrowed
-    fn calculate_internal_value(&self) {
+    pub(crate) fn calculate_internal_value(&mut self) {
         let mut value = self.base_value;
+        self.select_stacks();
+        self.order_modifiers();
+        let bonus_start = self
+            .modifiers
+            .partition_point(|m| m.tier == ModifierTier::Base);
+        let (base, bonus) = self.modifiers.split_at(bonus_start);
+        self.apply_modifiers(base, &mut value);
+        self.base_total = value;
+        self.apply_modifiers(bonus, &mut value);
+        self.value = value;
+        self.version = self.version.wrapping_add(1);
+    }
 
-        // Order the modifiers
-        let mut modifiers = borrow_cell(&self.modifiers);
-        Self::order_modifiers(&mut modifiers);
-        Self::apply_modifiers_to_value(modifiers, &mut value);
-        let mut internal_value = borrow_cell(&self.value);
-        *internal_value = value;
+    // the base tier comes first, so it can be applied on its own
+    fn order_modifiers(&mut self) {
+        self.modifiers.sort_by_key(|m| (m.tier, m.order));
     }
 
-    fn order_modifiers(modifiers: &mut RefMut<TinyVec<[ModifierMeta; M]>>) {
-        modifiers.sort_by(|m1, m2| m1.order.cmp(&m2.order));
+    // dropped modifiers are already removed by update_modifiers()
+    fn apply_modifiers_to_value(&self, value: &mut f32) {
+        self.apply_modifiers(&self.modifiers, value);
     }
 
-    fn apply_modifiers_to_value(
-        mut modifiers: RefMut<TinyVec<[ModifierMeta; M]>>,
-        value: &mut f32,
-    ) {
-        for modifier_meta in modifiers.iter_mut() {
-            if let Some(_key) = modifier_meta.owner_modifier_weak.upgrade() {
-                modifier_meta.modifier.apply(value);
-            }
+    // applied per order group, increased modifiers are added up within their group
+    fn apply_modifiers(&self, modifiers: &[ModifierMeta], value: &mut f32) {
+        for group in modifiers.chunk_by(|a, b| (a.tier, a.order) == (b.tier, b.order)) {
+            let modifiers = group.iter().filter(|m| !m.suppressed).map(|m| &m.modifier);
+            self.aggregation(group[0].order).apply(modifiers, value);
         }
     }
 }
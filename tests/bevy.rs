@@ -0,0 +1,155 @@
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use game_stat::ecs::*;
+use game_stat::prelude::*;
+use std::time::Duration;
+
+struct Armor;
+
+fn app() -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        GameStatPlugin,
+        StatComponentPlugin::<Armor, 2>::default(),
+        StatSheetPlugin::<&'static str, 2>::default(),
+    ))
+    .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+        200,
+    )));
+    app
+}
+
+fn armor(app: &App, entity: Entity) -> f32 {
+    app.world()
+        .get::<StatComponent<Armor, 2>>(entity)
+        .unwrap()
+        .value()
+}
+
+fn add_armor_modifier(app: &mut App, entity: Entity, modifier: StatModifier) -> StatModifierHandle {
+    app.world_mut()
+        .get_mut::<StatComponent<Armor, 2>>(entity)
+        .unwrap()
+        .add_modifier(modifier)
+}
+
+#[test]
+fn despawn_removes_modifier() {
+    let mut app = app();
+    let player = app
+        .world_mut()
+        .spawn(StatComponent::<Armor, 2>::new(10.0))
+        .id();
+    let handle = add_armor_modifier(&mut app, player, StatModifier::Flat(5.0));
+    let buff = app.world_mut().spawn(StatModifierComponent(handle)).id();
+    app.update();
+    assert_eq!(armor(&app, player), 15.0);
+
+    app.world_mut().despawn(buff);
+    app.update();
+    assert_eq!(armor(&app, player), 10.0);
+}
+
+#[test]
+fn timed_modifier() {
+    let mut app = app();
+    let player = app
+        .world_mut()
+        .spawn(StatComponent::<Armor, 2>::new(10.0))
+        .id();
+    let handle = add_armor_modifier(&mut app, player, StatModifier::Flat(5.0));
+    app.world_mut().spawn(TimedModifier::new(handle, 0.5));
+
+    // every update is 200ms (the first update has no time delta)
+    app.update();
+    app.update();
+    assert_eq!(armor(&app, player), 15.0);
+    for _ in 0..3 {
+        app.update();
+    }
+    assert_eq!(armor(&app, player), 10.0);
+}
+
+#[test]
+fn stat_changed_events() {
+    let mut app = app();
+    let mut cursor = app
+        .world()
+        .resource::<Events<StatChanged<Armor>>>()
+        .get_cursor();
+    let mut update = |app: &mut App| {
+        app.update();
+        let events = app.world().resource::<Events<StatChanged<Armor>>>();
+        cursor
+            .read(events)
+            .map(|event| (event.entity, event.old_value, event.new_value))
+            .collect::<Vec<_>>()
+    };
+
+    let player = app
+        .world_mut()
+        .spawn(StatComponent::<Armor, 2>::new(10.0))
+        .id();
+    assert_eq!(update(&mut app), vec![(player, None, 10.0)]);
+    let handle = add_armor_modifier(&mut app, player, StatModifier::Flat(5.0));
+    assert_eq!(update(&mut app), vec![(player, Some(10.0), 15.0)]);
+    // nothing changed
+    assert_eq!(update(&mut app), vec![]);
+    drop(handle);
+    assert_eq!(update(&mut app), vec![(player, Some(15.0), 10.0)]);
+}
+
+#[test]
+fn sheet_component() {
+    let mut app = app();
+    let mut sheet: StatSheet<&'static str, 2> = StatSheet::new();
+    sheet.insert("strength", Stat::new(10.0));
+    let player = app.world_mut().spawn(sheet).id();
+    app.update();
+    let sheet = app
+        .world()
+        .get::<StatSheet<&'static str, 2>>(player)
+        .unwrap();
+    assert_eq!(sheet.value("strength"), Some(10.0));
+}
+
+#[test]
+fn stats_are_ticked() {
+    let mut app = app();
+    let player = app
+        .world_mut()
+        .spawn(StatComponent::<Armor, 2>::new(10.0))
+        .id();
+    app.world_mut()
+        .get_mut::<StatComponent<Armor, 2>>(player)
+        .unwrap()
+        .apply_stacking(
+            StackingModifier::new("shield", StatModifier::Flat(5.0), StackingPolicy::Refresh)
+                .with_duration(0.5),
+        );
+    let mut sheet: StatSheet<&'static str, 2> = StatSheet::new();
+    sheet.insert("strength", Stat::new(10.0));
+    sheet.get_mut("strength").unwrap().apply_stacking(
+        StackingModifier::new("rage", StatModifier::Flat(5.0), StackingPolicy::Refresh)
+            .with_duration(0.5),
+    );
+    let sheet = app.world_mut().spawn(sheet).id();
+    let strength = |app: &App| {
+        app.world()
+            .get::<StatSheet<&'static str, 2>>(sheet)
+            .unwrap()
+            .value("strength")
+    };
+
+    // every update is 200ms (the first update has no time delta)
+    app.update();
+    app.update();
+    assert_eq!(armor(&app, player), 15.0);
+    assert_eq!(strength(&app), Some(15.0));
+    for _ in 0..3 {
+        app.update();
+    }
+    assert_eq!(armor(&app, player), 10.0);
+    assert_eq!(strength(&app), Some(10.0));
+}
@@ -1,4 +1,11 @@
 use game_stat::prelude::*;
+#[cfg(any(feature = "json", feature = "ron", feature = "toml"))]
+use game_stat::ModifierDefinitions;
+#[cfg(all(feature = "json", not(feature = "sync")))]
+use game_stat::ReloadError;
+#[cfg(feature = "json")]
+use game_stat::TemplateError;
+use game_stat::{CapacityPolicy, Curve, CurveError, Formula, Interpolation, Stacked};
 
 #[test]
 fn base_value() {
@@ -136,9 +143,10 @@ fn integrated_modifiers_dropped_later() {
     let _modifier_key = stat.add_modifier(StatModifier::Flat(10f32));
 
     let mut other_stat: Stat<1> = Stat::new(5f32);
-    let mut handles = vec![];
-    handles.push(other_stat.add_modifier(StatModifier::Flat(10f32)));
-    handles.push(other_stat.add_modifier(StatModifier::Flat(10f32)));
+    let handles = vec![
+        other_stat.add_modifier(StatModifier::Flat(10f32)),
+        other_stat.add_modifier(StatModifier::Flat(10f32)),
+    ];
 
     assert_eq!(stat.value_with_integrated_modifiers(&other_stat), 40f32);
     drop(handles);
@@ -170,6 +178,1303 @@ fn key_shadowing() {
     assert!(stat.value() == 0f32);
 }
 
+#[test]
+fn snapshot_restore() {
+    let mut stat: Stat<2> = Stat::new(10f32);
+    let _modifier_1 = stat.add_modifier(StatModifier::Flat(5f32));
+    let snapshot = stat.snapshot();
+
+    stat.set_base_value(0f32);
+    let _modifier_2 = stat.add_modifier(StatModifier::PercentMultiply(2f32));
+    assert_eq!(stat.value(), 10f32);
+
+    stat.restore(&snapshot);
+    assert_eq!(stat.value(), 15f32);
+    assert_eq!(stat.base_value(), 10f32);
+    // restoring twice should be fine, rollback netcode might do it every frame
+    stat.restore(&snapshot);
+    assert_eq!(stat.value(), 15f32);
+}
+
+#[test]
+fn snapshot_restore_keeps_order() {
+    let mut stat: Stat<2> = Stat::new(10f32);
+    let _multiply = stat.add_modifier_with_order(StatModifier::PercentMultiply(2f32), 0);
+    let _flat = stat.add_modifier_with_order(StatModifier::Flat(5f32), 1);
+    let snapshot = stat.snapshot();
+    assert_eq!(snapshot.modifier_count(), 2);
+
+    let mut other: Stat<2> = Stat::new(0f32);
+    other.restore(&snapshot);
+    assert_eq!(other.value(), 25f32);
+}
+
+#[test]
+// a snapshot doesn't own modifiers, a handle dropped after the snapshot was taken stays removed
+fn snapshot_restore_dropped_handle() {
+    let mut stat: Stat<2> = Stat::new(10f32);
+    let modifier = stat.add_modifier(StatModifier::Flat(5f32));
+    let kept_modifier = stat.add_modifier(StatModifier::Flat(1f32));
+    let snapshot = stat.snapshot();
+    drop(modifier);
+
+    stat.restore(&snapshot);
+    assert_eq!(stat.value(), 11f32);
+    drop(kept_modifier);
+    assert_eq!(stat.value(), 10f32);
+}
+
+#[test]
+fn set_base_value() {
+    let mut stat: Stat<2> = Stat::new(10f32);
+    let _modifier = stat.add_modifier(StatModifier::PercentMultiply(2f32));
+    stat.set_base_value(5f32);
+    assert_eq!(stat.base_value(), 5f32);
+    assert_eq!(stat.value(), 10f32);
+}
+
+#[test]
+// clones should behave exactly the same with and without the sync feature
+fn clone_is_independent() {
+    let mut stat: Stat<2> = Stat::new(10f32);
+    let modifier = stat.add_modifier(StatModifier::Flat(5f32));
+    let mut clone = stat.clone();
+    assert!(!clone.is_shared_with(&stat));
+    assert_eq!(clone.value(), 15f32);
+
+    clone.set_base_value(0f32);
+    let _clone_modifier = clone.add_modifier(StatModifier::Flat(1f32));
+    assert_eq!(stat.value(), 15f32);
+    assert_eq!(clone.value(), 6f32);
+
+    // copied modifiers are still owned by the original handle
+    drop(modifier);
+    assert_eq!(stat.value(), 10f32);
+    assert_eq!(clone.value(), 1f32);
+}
+
+#[test]
+fn fork_is_independent() {
+    let mut stat: Stat<2> = Stat::new(10f32);
+    let _modifier = stat.add_modifier(StatModifier::Flat(5f32));
+    let fork = stat.fork();
+    stat.set_base_value(0f32);
+    assert_eq!(stat.value(), 5f32);
+    assert_eq!(fork.value(), 15f32);
+}
+
+#[test]
+fn share_is_linked() {
+    let mut stat: Stat<2> = Stat::new(10f32);
+    let mut view = stat.share();
+    assert!(view.is_shared_with(&stat));
+
+    let modifier = stat.add_modifier(StatModifier::Flat(5f32));
+    assert_eq!(view.value(), 15f32);
+    view.set_base_value(0f32);
+    assert_eq!(stat.value(), 5f32);
+    drop(modifier);
+    assert_eq!(view.value(), 0f32);
+
+    // integrating a shared view of yourself shouldn't deadlock
+    let _view_modifier = view.add_modifier(StatModifier::Flat(1f32));
+    assert_eq!(stat.value_with_integrated_modifiers(&view), 2f32);
+}
+
+#[test]
+fn try_api() -> Result<(), StatError> {
+    let mut stat: Stat<2> = Stat::new(10f32);
+    {
+        let _modifier = stat.try_add_modifier(StatModifier::Flat(5f32))?;
+        let _modifier_with_order =
+            stat.try_add_modifier_with_order(StatModifier::PercentMultiply(2f32), -1)?;
+        assert_eq!(stat.try_value()?, 25f32);
+        assert_eq!(stat.try_highest_order()?, 0);
+        assert_eq!(stat.try_value_with_base(0f32)?, 5f32);
+    }
+    stat.try_set_base_value(1f32)?;
+    assert_eq!(stat.try_base_value()?, 1f32);
+    assert_eq!(stat.try_value(), Ok(1f32));
+    Ok(())
+}
+
+// a locked mutex blocks instead of returning StatError::Borrowed
+#[cfg(not(feature = "sync"))]
+#[test]
+fn try_api_borrowed() {
+    let mut stat: Stat<2> = Stat::new(10f32);
+    let view = stat.share();
+    let result = stat.batch(|batch| {
+        batch.set_base_value(20f32);
+        view.try_value()
+    });
+    assert_eq!(result, Err(StatError::Borrowed));
+    assert_eq!(view.try_value(), Ok(20f32));
+}
+
+// a spin lock can't be poisoned
+#[cfg(all(feature = "sync", not(feature = "spin")))]
+#[test]
+fn try_api_poisoned() {
+    let mut stat: Stat<2> = Stat::new(10f32);
+    let mut view = stat.share();
+    let result = std::thread::spawn(move || view.batch(|_| panic!("panic while locked"))).join();
+    assert!(result.is_err());
+    assert_eq!(stat.try_value(), Err(StatError::Poisoned));
+    assert_eq!(
+        stat.try_add_modifier(StatModifier::Flat(1f32)).unwrap_err(),
+        StatError::Poisoned
+    );
+}
+
+#[test]
+fn stat_error_display() {
+    assert_eq!(StatError::Borrowed.to_string(), "stat is already borrowed");
+}
+
+#[test]
+fn strict_capacity() {
+    let mut stat: Stat<2> = Stat::new_strict(0f32);
+    assert_eq!(stat.capacity_policy(), CapacityPolicy::Strict);
+    let _modifier_1 = stat.add_modifier(StatModifier::Flat(1.0f32));
+    {
+        let _modifier_2 = stat.add_modifier(StatModifier::Flat(1.0f32));
+        assert_eq!(
+            stat.try_add_modifier(StatModifier::Flat(1.0f32))
+                .unwrap_err(),
+            StatError::CapacityExceeded { capacity: 2 }
+        );
+        assert_eq!(stat.value(), 2f32);
+    }
+    // a dropped modifier frees up space again
+    let _modifier_3 = stat.add_modifier(StatModifier::Flat(1.0f32));
+    assert_eq!(stat.value(), 2f32);
+    assert_eq!(stat.heap_spills(), 0);
+}
+
+#[test]
+fn heap_spills() {
+    let mut stat: Stat<1> = Stat::new(0f32);
+    let _modifier_1 = stat.add_modifier(StatModifier::Flat(1.0f32));
+    assert_eq!(stat.heap_spills(), 0);
+    let _modifier_2 = stat.add_modifier(StatModifier::Flat(1.0f32));
+    let _modifier_3 = stat.add_modifier(StatModifier::Flat(1.0f32));
+    assert_eq!(stat.heap_spills(), 1);
+    assert_eq!(stat.value(), 3f32);
+}
+
+#[test]
+// the restored modifiers belong to another stat, dropping their handle should still be noticed
+fn snapshot_restore_other_stat() {
+    let mut stat: Stat<2> = Stat::new(10f32);
+    let modifier = stat.add_modifier(StatModifier::Flat(5f32));
+    let snapshot = stat.snapshot();
+    drop(stat);
+
+    let mut other: Stat<2> = Stat::new(0f32);
+    other.restore(&snapshot);
+    drop(snapshot);
+    let other_modifier = other.add_modifier(StatModifier::Flat(1f32));
+    assert_eq!(other.value(), 16f32);
+    drop(modifier);
+    assert_eq!(other.value(), 11f32);
+    drop(other_modifier);
+    assert_eq!(other.value(), 10f32);
+}
+
+#[test]
+fn keyed_modifiers() {
+    let mut stat: Stat<2> = Stat::new(10f32);
+    let flat = stat.insert_modifier(StatModifier::Flat(5f32));
+    let multiply = stat.insert_modifier_with_order(StatModifier::PercentMultiply(2f32), -1);
+    assert_eq!(stat.value(), 25f32);
+    assert!(stat.contains_modifier(flat));
+
+    assert!(stat.remove_modifier(multiply));
+    assert!(!stat.remove_modifier(multiply));
+    assert_eq!(stat.value(), 15f32);
+
+    // keys are never reused, the old key can't remove the new modifier
+    assert!(stat.remove_modifier(flat));
+    let new_flat = stat.insert_modifier(StatModifier::Flat(1f32));
+    assert_ne!(flat, new_flat);
+    assert!(!stat.remove_modifier(flat));
+    assert_eq!(stat.value(), 11f32);
+}
+
+#[test]
+fn keyed_modifiers_snapshot() {
+    let mut stat: Stat<2> = Stat::new(10f32);
+    let snapshot = stat.snapshot();
+    let key = stat.insert_modifier(StatModifier::Flat(5f32));
+    stat.restore(&snapshot);
+    assert_eq!(stat.value(), 10f32);
+
+    // after a rollback the same keys are handed out again
+    assert_eq!(stat.insert_modifier(StatModifier::Flat(5f32)), key);
+    assert_eq!(stat.value(), 15f32);
+
+    let handle = stat.add_modifier(StatModifier::Flat(1f32));
+    let snapshot = stat.snapshot();
+    let mut other: Stat<2> = Stat::new(0f32);
+    other.restore(&snapshot);
+    // the keys of the snapshot are taken in the other stat as well
+    assert_ne!(
+        other.insert_modifier(StatModifier::Flat(100f32)),
+        handle.key()
+    );
+    assert_eq!(other.value(), 116f32);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serialize_keyed_modifiers() {
+    let mut stat: Stat<2> = Stat::new(10f32);
+    let key = stat.insert_modifier(StatModifier::Flat(5f32));
+    // handles can't be serialized, so this modifier is skipped
+    let _handle = stat.add_modifier(StatModifier::PercentMultiply(2f32));
+
+    let json = serde_json::to_string(&stat).unwrap();
+    let mut deserialized: Stat<2> = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized.value(), 15f32);
+    assert!(deserialized.remove_modifier(key));
+    assert_eq!(deserialized.value(), 10f32);
+
+    let old_format: Stat<2> = serde_json::from_str(r#"{"base_value":3.0}"#).unwrap();
+    assert_eq!(old_format.value(), 3f32);
+
+    let mut tiered: Stat<2> = Stat::new(10f32);
+    let key = tiered.insert_base_modifier(StatModifier::Flat(5f32));
+    tiered.insert_modifier(StatModifier::Flat(1f32));
+    let json = serde_json::to_string(&tiered).unwrap();
+    let deserialized: Stat<2> = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized.base_total(), 15f32);
+    assert_eq!(deserialized.bonus(), 1f32);
+    assert!(deserialized.contains_modifier(key));
+}
+
+#[test]
+fn remove_handle_modifier_by_key() {
+    let mut stat: Stat<2> = Stat::new(10f32);
+    let handle = stat.add_modifier(StatModifier::Flat(5f32));
+    let key = handle.key();
+    assert!(stat.contains_modifier(key));
+    assert!(stat.remove_modifier(key));
+    assert_eq!(stat.value(), 10f32);
+    // dropping the handle afterwards does nothing
+    drop(handle);
+    assert_eq!(stat.value(), 10f32);
+}
+
+#[test]
+fn detach_modifier() {
+    let mut stat: Stat<2> = Stat::new(10f32);
+    let key = {
+        let handle = stat.add_modifier(StatModifier::Flat(5f32));
+        stat.detach_modifier(handle).unwrap()
+    };
+    assert_eq!(stat.value(), 15f32);
+    assert!(stat.remove_modifier(key));
+    assert_eq!(stat.value(), 10f32);
+
+    // a handle from another stat can't be detached
+    let mut other: Stat<2> = Stat::new(0f32);
+    let other_handle = other.add_modifier(StatModifier::Flat(1f32));
+    assert_eq!(stat.detach_modifier(other_handle), None);
+    assert_eq!(other.value(), 0f32);
+}
+
+#[test]
+fn batch() {
+    let mut stat: Stat<2> = Stat::new(10f32);
+    let key = stat.insert_modifier(StatModifier::Flat(100f32));
+    let (handles, removed) = stat.batch(|batch| {
+        batch.set_base_value(0f32);
+        let removed = batch.remove_modifier(key);
+        let handles = [
+            batch.add_modifier(StatModifier::PercentMultiply(2f32)),
+            batch.add_modifier(StatModifier::Flat(2f32)),
+            batch.add_modifier_with_order(StatModifier::Flat(1f32), 5),
+        ];
+        batch.insert_modifier(StatModifier::Flat(3f32));
+        (handles, removed)
+    });
+    assert!(removed);
+    // modifiers are still applied in order, even though they were added in a different order
+    assert_eq!(stat.value(), 11f32);
+    drop(handles);
+    assert_eq!(stat.value(), 3f32);
+}
+
+#[test]
+fn batch_strict_capacity() {
+    let mut stat: Stat<1> = Stat::new_strict(0f32);
+    let result = stat.batch(|batch| {
+        let _first = batch.try_add_modifier(StatModifier::Flat(1f32))?;
+        batch.try_add_modifier(StatModifier::Flat(1f32))
+    });
+    assert_eq!(
+        result.unwrap_err(),
+        StatError::CapacityExceeded { capacity: 1 }
+    );
+    assert_eq!(stat.value(), 0f32);
+}
+
+#[test]
+fn stat_sheet() {
+    let mut sheet: StatSheet<String, 2> = [
+        ("strength".to_string(), Stat::new(10f32)),
+        ("agility".to_string(), Stat::new(5f32)),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(sheet.len(), 2);
+    let _modifier = sheet
+        .get_mut("agility")
+        .unwrap()
+        .add_modifier(StatModifier::Flat(1f32));
+    assert_eq!(sheet.value("agility"), Some(6f32));
+    assert_eq!(sheet.value("luck"), None);
+    assert!(sheet.remove("strength").is_some());
+    assert!(!sheet.contains("strength"));
+}
+
+#[test]
+fn derived_stat() {
+    let mut strength: Stat<2> = Stat::new(10.0);
+    let mut agility: Stat<2> = Stat::new(20.0);
+    let mut attack_power: DerivedStat<2> =
+        DerivedStat::new([strength.source(), agility.source()], |v| 2.0 * v[0] + v[1]);
+    let crit_chance: DerivedStat<2> = DerivedStat::new([agility.source()], |v| 0.05 + v[0] / 20.0);
+    assert_eq!(attack_power.value(), 40.0);
+    assert_eq!(crit_chance.value(), 1.05);
+
+    let _sword = attack_power.add_modifier(StatModifier::Flat(10.0));
+    assert_eq!(attack_power.base_value(), 40.0);
+    assert_eq!(attack_power.value(), 50.0);
+    {
+        let _belt = strength.add_modifier(StatModifier::Flat(5.0));
+        assert_eq!(attack_power.value(), 60.0);
+    }
+    assert_eq!(attack_power.value(), 50.0);
+    agility.set_base_value(0.0);
+    assert_eq!(attack_power.value(), 30.0);
+    assert_eq!(crit_chance.value(), 0.05);
+
+    // derived stats can be inputs too
+    let damage: DerivedStat<2> = DerivedStat::new([attack_power.source()], |v| v[0] * 3.0);
+    assert_eq!(damage.value(), 90.0);
+    strength.set_base_value(20.0);
+    assert_eq!(damage.value(), 150.0);
+}
+
+#[test]
+fn derived_stat_cycle() {
+    let strength: Stat<2> = Stat::new(10.0);
+    let mut a: DerivedStat<2> = DerivedStat::new([strength.source()], |v| v[0]);
+    let b: DerivedStat<2> = DerivedStat::new([a.source()], |v| v[0]);
+    assert_eq!(
+        a.try_set_formula([a.source()], |v| v[0]),
+        Err(StatError::Cycle)
+    );
+    assert_eq!(
+        a.try_set_formula([strength.source(), b.source()], |v| v[0] + v[1]),
+        Err(StatError::Cycle)
+    );
+    assert_eq!(b.value(), 10.0);
+    assert_eq!(
+        a.try_set_formula([strength.source()], |v| v[0] * 2.0),
+        Ok(())
+    );
+    assert_eq!(b.value(), 20.0);
+}
+
+#[test]
+fn scaled_modifier() {
+    let mut strength: Stat<2> = Stat::new(50.0);
+    let mut damage: Stat<2> = Stat::new(100.0);
+    // +10% damage per 10 strength
+    let handle = damage.add_scaled_modifier(&strength.source(), |strength| {
+        StatModifier::PercentAdd(strength / 100.0)
+    });
+    assert_eq!(damage.value(), 150.0);
+    {
+        let _ring = strength.add_modifier(StatModifier::Flat(50.0));
+        assert_eq!(damage.value(), 200.0);
+    }
+    assert_eq!(damage.value(), 150.0);
+    drop(handle);
+    assert_eq!(damage.value(), 100.0);
+
+    // the source is only weakly referenced, the modifier keeps its last value
+    let mut max_health: Stat<2> = Stat::new(100.0);
+    let mut armor: Stat<2> = Stat::new(10.0);
+    let key = armor.insert_scaled_modifier(&max_health.source(), |health| {
+        StatModifier::Flat(health * 0.2)
+    });
+    max_health.set_base_value(200.0);
+    assert_eq!(armor.value(), 50.0);
+    drop(max_health);
+    assert_eq!(armor.value(), 50.0);
+    assert!(armor.remove_modifier(key));
+    assert_eq!(armor.value(), 10.0);
+}
+
+#[test]
+fn scaled_modifier_snapshot() {
+    let mut max_health: Stat<2> = Stat::new(100.0);
+    let mut armor: Stat<2> = Stat::new(10.0);
+    let key = armor.insert_scaled_modifier(&max_health.source(), |health| {
+        StatModifier::Flat(health * 0.2)
+    });
+    let snapshot = armor.snapshot();
+    armor.remove_modifier(key);
+    assert_eq!(armor.value(), 10.0);
+    // the restored modifier follows the source again
+    armor.restore(&snapshot);
+    max_health.set_base_value(200.0);
+    assert_eq!(armor.value(), 50.0);
+}
+
+#[test]
+fn scaled_modifier_snapshot_cycle() {
+    let mut health: Stat<2> = Stat::new(100.0);
+    let mut armor: Stat<2> = Stat::new(10.0);
+    armor.insert_scaled_modifier(&health.source(), |health| StatModifier::Flat(health * 0.2));
+    // health would read from itself
+    assert_eq!(health.try_restore(&armor.snapshot()), Err(StatError::Cycle));
+    assert_eq!(health.value(), 100.0);
+    assert_eq!(armor.value(), 30.0);
+}
+
+#[test]
+fn scaled_modifier_cycle() {
+    let mut health: Stat<2> = Stat::new(100.0);
+    let mut armor: Stat<2> = Stat::new(10.0);
+    let _handle = armor.add_scaled_modifier(&health.source(), StatModifier::Flat);
+    assert_eq!(
+        health
+            .try_add_scaled_modifier(&armor.source(), StatModifier::Flat)
+            .unwrap_err(),
+        StatError::Cycle
+    );
+    assert_eq!(
+        health
+            .try_add_scaled_modifier(&health.source(), StatModifier::Flat)
+            .unwrap_err(),
+        StatError::Cycle
+    );
+    let derived: DerivedStat<2> = DerivedStat::new([armor.source()], |v| v[0]);
+    assert_eq!(
+        health
+            .try_add_scaled_modifier(&derived.source(), StatModifier::Flat)
+            .unwrap_err(),
+        StatError::Cycle
+    );
+    assert_eq!(derived.value(), 110.0);
+}
+
+#[test]
+fn stat_graph() {
+    let mut graph: StatGraph<&str, 2> = StatGraph::new();
+    graph.insert("strength", Stat::new(10.0));
+    graph.insert_derived("attack_power", ["strength"], |v| v[0] * 2.0);
+    graph.insert_derived("damage", ["attack_power", "weapon"], |v| v[0] + v[1]);
+    assert!(graph.is_dirty("damage"));
+    // missing inputs are 0
+    assert_eq!(graph.value("damage"), Some(20.0));
+    assert!(!graph.is_dirty("damage"));
+
+    graph.insert("weapon", Stat::new(5.0));
+    assert!(graph.is_dirty("damage"));
+    assert!(!graph.is_dirty("attack_power"));
+    // only what is read is recalculated
+    let handle = graph
+        .get_mut("strength")
+        .unwrap()
+        .add_modifier(StatModifier::Flat(5.0));
+    assert!(graph.is_dirty("attack_power"));
+    assert_eq!(graph.value("attack_power"), Some(30.0));
+    assert!(graph.is_dirty("damage"));
+    graph.update();
+    assert!(!graph.is_dirty("damage"));
+    assert_eq!(graph.get("damage").unwrap().value(), 35.0);
+
+    // the graph doesn't see the handle being dropped, but notices when reading
+    drop(handle);
+    assert_eq!(graph.value("damage"), Some(25.0));
+
+    let _buff = graph
+        .get_mut("damage")
+        .unwrap()
+        .add_modifier(StatModifier::PercentMultiply(2.0));
+    assert_eq!(graph.value("damage"), Some(50.0));
+    graph.remove("weapon");
+    assert_eq!(graph.value("damage"), Some(40.0));
+}
+
+#[test]
+fn stat_graph_cycle() {
+    let mut graph: StatGraph<&str, 2> = StatGraph::new();
+    graph.insert_derived("b", ["a"], |v| v[0] + 1.0);
+    graph.insert_derived("c", ["b"], |v| v[0] + 1.0);
+    assert_eq!(
+        graph.try_insert_derived("a", ["c"], |v| v[0]),
+        Err(StatError::Cycle)
+    );
+    assert_eq!(
+        graph.try_insert_derived("b", ["b"], |v| v[0]),
+        Err(StatError::Cycle)
+    );
+    graph.insert("a", Stat::new(1.0));
+    graph.update();
+    assert_eq!(graph.get("c").unwrap().value(), 3.0);
+}
+
+#[test]
+fn stat_graph_insert_after_update() {
+    let mut graph: StatGraph<&str, 2> = StatGraph::new();
+    graph.insert_derived("attack_power", ["strength"], |v| v[0] * 2.0);
+    graph.update();
+    // a new key has to be part of the next update
+    graph.insert("strength", Stat::new(10.0));
+    let handle = graph
+        .get_mut("strength")
+        .unwrap()
+        .add_modifier(StatModifier::Flat(5.0));
+    graph.update();
+    assert_eq!(graph.get("attack_power").unwrap().value(), 30.0);
+    drop(handle);
+    graph.update();
+    assert_eq!(graph.get("attack_power").unwrap().value(), 20.0);
+}
+
+#[test]
+fn formula() {
+    let formula = Formula::parse("base * (1 + str / 100) + flat_bonus").unwrap();
+    assert_eq!(formula.evaluate(&[10.0, 50.0, 5.0]), 20.0);
+    let formula: Formula = "clamp(-x, 0, 10) + min(x, 3, y) - max(2) * abs(-1)"
+        .parse()
+        .unwrap();
+    assert_eq!(formula.variables(), ["x", "y"]);
+    assert_eq!(formula.evaluate(&[-20.0, 1.0]), 10.0 - 20.0 - 2.0);
+    // piecewise
+    let formula = Formula::parse("if(level < 10, level * 2, 20 + (level - 10))").unwrap();
+    assert_eq!(formula.evaluate(&[5.0]), 10.0);
+    assert_eq!(formula.evaluate(&[15.0]), 25.0);
+    assert_eq!(Formula::parse("1 + 2 * 3 >= 7").unwrap().evaluate(&[]), 1.0);
+}
+
+#[test]
+fn formula_depth() {
+    let nested =
+        |depth: usize| Formula::parse(&format!("{}1{}", "(".repeat(depth), ")".repeat(depth)));
+    assert_eq!(nested(64).unwrap().evaluate(&[]), 1.0);
+    let error = nested(500).unwrap_err();
+    assert_eq!(error.message(), "nested more than 64 levels deep");
+    // the span is the 65th level
+    assert_eq!(error.span(), 64..65);
+    let error = Formula::parse(&format!("{}1", "-".repeat(500))).unwrap_err();
+    assert_eq!(error.span(), 64..65);
+    let error = Formula::parse(&format!("{}1{}", "abs(".repeat(500), ")".repeat(500))).unwrap_err();
+    assert_eq!(error.span(), 256..259);
+    assert!(Formula::parse(&format!("{}1{}", "-(".repeat(32), ")".repeat(32))).is_ok());
+    assert!(Formula::parse(&format!("{}1", "-".repeat(64))).is_ok());
+}
+
+#[test]
+fn formula_errors() {
+    let error = |source: &str| {
+        let error = Formula::parse(source).unwrap_err();
+        (error.to_string(), error.span())
+    };
+    assert_eq!(
+        error("str # 2"),
+        ("unexpected character `#` at 4..5".to_string(), 4..5)
+    );
+    assert_eq!(
+        error("(str + 2"),
+        ("expected `)` at 8..8".to_string(), 8..8)
+    );
+    assert_eq!(
+        error("str + * 2"),
+        ("expected an expression at 6..7".to_string(), 6..7)
+    );
+    assert_eq!(
+        error("str 2"),
+        ("expected an operator at 4..5".to_string(), 4..5)
+    );
+    assert_eq!(
+        error("1 + sqrt(str)"),
+        ("unknown function `sqrt` at 4..8".to_string(), 4..8)
+    );
+    assert_eq!(
+        error("1 + clamp(str, 2)"),
+        (
+            "`clamp` takes 3 arguments, found 2 at 4..17".to_string(),
+            4..17
+        )
+    );
+
+    let formula = Formula::parse("str + agi").unwrap();
+    let error = DerivedStat::<2>::from_formula(&formula, |_| None).unwrap_err();
+    assert_eq!(error.to_string(), "unknown stat `str` at 0..3");
+}
+
+// a locked mutex blocks instead of returning StatError::Borrowed
+#[cfg(not(feature = "sync"))]
+#[test]
+fn formula_stats_borrowed() {
+    let mut strength: Stat<2> = Stat::new(50.0);
+    let source = strength.source();
+    let formula = Formula::parse("str * 2").unwrap();
+    let (formula_result, result) = strength.batch(|_| {
+        (
+            DerivedStat::<2>::from_formula(&formula, |_| Some(source.clone())),
+            DerivedStat::<2>::try_new([source.clone()], |v| v[0]),
+        )
+    });
+    let error = formula_result.unwrap_err();
+    assert_eq!(error.stat_error(), Some(StatError::Borrowed));
+    assert_eq!(error.span(), 0..7);
+    assert_eq!(result.unwrap_err(), StatError::Borrowed);
+}
+
+#[test]
+fn formula_stats() {
+    let mut strength: Stat<2> = Stat::new(50.0);
+    let formula = Formula::parse("10 * (1 + str / 100)").unwrap();
+    let attack_power: DerivedStat<2> = DerivedStat::from_formula(&formula, |name| match name {
+        "str" => Some(strength.source()),
+        _ => None,
+    })
+    .unwrap();
+    assert_eq!(attack_power.value(), 15.0);
+    strength.set_base_value(100.0);
+    assert_eq!(attack_power.value(), 20.0);
+
+    let mut graph: StatGraph<String, 2> = StatGraph::new();
+    graph.insert("str".to_string(), Stat::new(50.0));
+    graph.insert_formula("attack_power".to_string(), &formula);
+    assert_eq!(graph.value("attack_power"), Some(15.0));
+    assert_eq!(
+        graph.try_insert_formula("str".to_string(), &"attack_power / 2".parse().unwrap()),
+        Err(StatError::Cycle)
+    );
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn modifier_definitions_json() {
+    let mut definitions = ModifierDefinitions::from_json_str(
+        r#"{
+            "fire_sword_bonus": { "stat": "attack", "modifier": { "Flat": 5.0 }, "tags": ["fire"] },
+            "rage": { "stat": "attack", "modifier": { "PercentMultiply": 2.0 }, "order": 10, "duration": 10.0 },
+            "iron_skin": { "stat": "armor", "modifier": { "PercentAdd": 0.5 } }
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(definitions.len(), 3);
+    let rage = definitions.get("rage").unwrap();
+    assert_eq!(rage.duration, Some(10.0));
+    assert_eq!(rage.order(), 10);
+    let fire: Vec<_> = definitions.tagged("fire").map(|(name, _)| name).collect();
+    assert_eq!(fire, ["fire_sword_bonus"]);
+
+    let mut sheet: StatSheet<&str, 2> = StatSheet::new();
+    sheet.insert("attack", Stat::new(10.0));
+    {
+        let _sword = definitions
+            .instantiate("fire_sword_bonus", &mut sheet)
+            .unwrap();
+        let _rage = definitions.instantiate("rage", &mut sheet).unwrap();
+        assert_eq!(sheet.value("attack"), Some(30.0));
+        // rage runs out after 10 seconds, even with the handle alive
+        sheet.tick(5.0);
+        assert_eq!(sheet.value("attack"), Some(30.0));
+        sheet.tick(5.0);
+        assert_eq!(sheet.value("attack"), Some(15.0));
+    }
+    assert_eq!(sheet.value("attack"), Some(10.0));
+    assert_eq!(
+        definitions
+            .instantiate("iron_skin", &mut sheet)
+            .unwrap_err(),
+        TemplateError::UnknownStat("armor".to_string())
+    );
+    assert_eq!(
+        definitions
+            .instantiate("ice_sword", &mut sheet)
+            .unwrap_err(),
+        TemplateError::UnknownTemplate("ice_sword".to_string())
+    );
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn modifier_definitions_errors() {
+    let error = |source: &str| {
+        ModifierDefinitions::from_json_str(source)
+            .unwrap_err()
+            .to_string()
+    };
+    assert_eq!(
+        error(
+            r#"{
+                "rage": { "stat": "attack", "modifier": { "Flat": 1.0 } },
+                "fire_sword_bonus": { "stat": "attack", "modifier": { "Flat": 1.0 }, "duration": -1.0 }
+            }"#
+        ),
+        "3:103: duration -1 has to be more than 0"
+    );
+    assert_eq!(
+        error(
+            r#"{
+                "rage": { "stat": "attack", "modifier": { "Flat": 1.0 } },
+                "rage": { "stat": "attack", "modifier": { "Flat": 1.0 } }
+            }"#
+        ),
+        "3:22: template `rage` is defined twice"
+    );
+    assert_eq!(
+        error(
+            r#"{
+                "rage": { "stat": "attack", "modifer": { "Flat": 1.0 } }
+            }"#
+        ),
+        "2:53: unknown field `modifer`, expected one of `stat`, `modifier`, `order`, `tags`, `duration`"
+    );
+    assert_eq!(
+        error(
+            r#"{ "armor_dr": { "stat": "armor", "modifier": { "DiminishingReturns": { "max": 1.0, "k": 0.0 } } } }"#
+        ),
+        "1:97: diminishing returns k 0 has to be more than 0"
+    );
+}
+
+#[cfg(feature = "ron")]
+#[test]
+fn modifier_definitions_ron() {
+    let definitions = ModifierDefinitions::from_ron_str(
+        r#"{
+            "fire_sword_bonus": (stat: "attack", modifier: Flat(5.0), tags: ["fire"]),
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        definitions.get("fire_sword_bonus").unwrap().modifier,
+        StatModifier::Flat(5.0)
+    );
+    let error = ModifierDefinitions::from_ron_str(
+        r#"{
+            "fire_sword_bonus": (stat: "attack", modifier: Flat(5.0)),
+            "rage": (stat: "", modifier: Flat(5.0)),
+        }"#,
+    )
+    .unwrap_err();
+    assert_eq!(error.line, Some(3));
+    assert_eq!(error.message, "`stat` can't be empty");
+}
+
+#[cfg(feature = "toml")]
+#[test]
+fn modifier_definitions_toml() {
+    let definitions = ModifierDefinitions::from_toml_str(
+        r#"
+[fire_sword_bonus]
+stat = "attack"
+modifier = { Flat = 5.0 }
+tags = ["fire"]
+"#,
+    )
+    .unwrap();
+    assert_eq!(definitions.get("fire_sword_bonus").unwrap().tags, ["fire"]);
+    let error = ModifierDefinitions::from_toml_str(
+        r#"
+[fire_sword_bonus]
+stat = "attack"
+modifier = { Flat = 5.0 }
+
+[rage]
+stat = "attack"
+modifier = { Flat = 5.0 }
+tags = [""]
+"#,
+    )
+    .unwrap_err();
+    assert_eq!(error.line, Some(9));
+    assert_eq!(error.message, "tags can't be empty");
+}
+
+#[cfg(all(feature = "json", feature = "toml"))]
+#[test]
+fn modifier_definitions_load() {
+    let dir = std::env::temp_dir().join("game_stat_definitions");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("buffs.toml");
+    std::fs::write(
+        &path,
+        "[rage]\nstat = \"attack\"\nmodifier = { Flat = 5.0 }\nduration = 0\n",
+    )
+    .unwrap();
+    let error = ModifierDefinitions::load(&path).unwrap_err();
+    assert_eq!(error.file, Some(path.display().to_string()));
+    assert_eq!(error.line, Some(4));
+    assert!(error
+        .to_string()
+        .ends_with("buffs.toml:4:12: duration 0 has to be more than 0"));
+
+    let path = dir.join("buffs.json");
+    std::fs::write(
+        &path,
+        r#"{ "rage": { "stat": "attack", "modifier": { "Flat": 5.0 } } }"#,
+    )
+    .unwrap();
+    assert_eq!(ModifierDefinitions::load(&path).unwrap().len(), 1);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn modifier_definitions_reload() {
+    let mut definitions = ModifierDefinitions::from_json_str(
+        r#"{
+            "fire_sword_bonus": { "stat": "attack", "modifier": { "Flat": 5.0 } },
+            "iron_skin": { "stat": "armor", "modifier": { "Flat": 2.0 } }
+        }"#,
+    )
+    .unwrap();
+    let mut sheet: StatSheet<&str, 2> = StatSheet::new();
+    sheet.insert("attack", Stat::new(10.0));
+    sheet.insert("armor", Stat::new(1.0));
+    let _sword = definitions
+        .instantiate("fire_sword_bonus", &mut sheet)
+        .unwrap();
+    let _skin = definitions.instantiate("iron_skin", &mut sheet).unwrap();
+    {
+        // dropped before the reload, nothing to update
+        let _sword = definitions
+            .instantiate("fire_sword_bonus", &mut sheet)
+            .unwrap();
+    }
+
+    let report = definitions.reload(
+        ModifierDefinitions::from_json_str(
+            r#"{
+                "fire_sword_bonus": { "stat": "attack", "modifier": { "Flat": 8.0 } },
+                "iron_skin": { "stat": "armor", "modifier": { "Flat": 2.0 }, "tags": ["metal"] },
+                "rage": { "stat": "attack", "modifier": { "PercentMultiply": 2.0 } }
+            }"#,
+        )
+        .unwrap(),
+    );
+    assert_eq!(report.added, ["rage"]);
+    assert!(report.removed.is_empty());
+    assert_eq!(report.changed, ["fire_sword_bonus", "iron_skin"]);
+    assert_eq!(report.affected_stats, ["attack"]);
+    assert_eq!(report.updated_modifiers, 1);
+    assert_eq!(sheet.value("attack"), Some(18.0));
+    assert_eq!(sheet.value("armor"), Some(3.0));
+
+    let report = definitions.reload(definitions.clone());
+    assert!(report.is_empty());
+    assert!(report.affected_stats.is_empty());
+}
+
+// a locked mutex blocks instead of returning StatError::Borrowed
+#[cfg(all(feature = "json", not(feature = "sync")))]
+#[test]
+fn modifier_definitions_reload_error() {
+    let mut definitions = ModifierDefinitions::from_json_str(
+        r#"{ "fire_sword_bonus": { "stat": "attack", "modifier": { "Flat": 5.0 } } }"#,
+    )
+    .unwrap();
+    let mut player: StatSheet<&str, 2> = StatSheet::new();
+    player.insert("attack", Stat::new(10.0));
+    let mut enemy = player.clone();
+    let _player_sword = definitions
+        .instantiate("fire_sword_bonus", &mut player)
+        .unwrap();
+    let _enemy_sword = definitions
+        .instantiate("fire_sword_bonus", &mut enemy)
+        .unwrap();
+    let new = ModifierDefinitions::from_json_str(
+        r#"{ "fire_sword_bonus": { "stat": "attack", "modifier": { "Flat": 8.0 } } }"#,
+    )
+    .unwrap();
+
+    // the player is updated first, then the enemy is borrowed
+    let result = enemy
+        .get_mut("attack")
+        .unwrap()
+        .batch(|_| definitions.try_reload(new.clone()));
+    assert_eq!(result, Err(StatError::Borrowed));
+    assert_eq!(player.value("attack"), Some(15.0));
+    assert_eq!(enemy.value("attack"), Some(15.0));
+    // the old templates are kept, so trying again still updates the modifiers
+    let report = definitions.reload(new);
+    assert_eq!(report.changed, ["fire_sword_bonus"]);
+    assert_eq!(report.updated_modifiers, 2);
+    assert_eq!(player.value("attack"), Some(18.0));
+    assert_eq!(enemy.value("attack"), Some(18.0));
+}
+
+// a locked mutex blocks instead of returning StatError::Borrowed
+#[cfg(all(feature = "json", not(feature = "sync")))]
+#[test]
+fn modifier_definitions_reload_file() {
+    let dir = std::env::temp_dir().join("game_stat_reload");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("buffs.json");
+    let write = |flat: f32| {
+        std::fs::write(
+            &path,
+            format!(r#"{{ "rage": {{ "stat": "attack", "modifier": {{ "Flat": {flat} }} }} }}"#),
+        )
+        .unwrap()
+    };
+    write(5.0);
+    let mut definitions = ModifierDefinitions::load(&path).unwrap();
+    let mut sheet: StatSheet<&str, 2> = StatSheet::new();
+    sheet.insert("attack", Stat::new(10.0));
+    let _rage = definitions.instantiate("rage", &mut sheet).unwrap();
+
+    write(8.0);
+    let result = sheet
+        .get_mut("attack")
+        .unwrap()
+        .batch(|_| definitions.reload_file(&path));
+    assert_eq!(result, Err(ReloadError::Stat(StatError::Borrowed)));
+    assert!(matches!(
+        definitions.reload_file(dir.join("missing.json")),
+        Err(ReloadError::Definition(_))
+    ));
+    assert_eq!(definitions.reload_file(&path).unwrap().changed, ["rage"]);
+    assert_eq!(sheet.value("attack"), Some(18.0));
+}
+
+#[test]
+fn stacking_policies() {
+    let mut stat: Stat<4> = Stat::new(10.0);
+    let poison = |policy| {
+        StackingModifier::new("poison", StatModifier::Flat(-2.0), policy).with_duration(5.0)
+    };
+
+    let Stacked::Added(key) = stat.apply_stacking(poison(StackingPolicy::Unique)) else {
+        panic!("first application is added");
+    };
+    assert_eq!(
+        stat.apply_stacking(poison(StackingPolicy::Unique)),
+        Stacked::Ignored
+    );
+    assert_eq!(stat.value(), 8.0);
+
+    stat.tick(3.0);
+    assert_eq!(stat.remaining_duration(key), Some(2.0));
+    assert_eq!(
+        stat.apply_stacking(poison(StackingPolicy::Refresh)),
+        Stacked::Refreshed(key)
+    );
+    assert_eq!(stat.remaining_duration(key), Some(5.0));
+
+    for _ in 0..4 {
+        stat.apply_stacking(poison(StackingPolicy::Stack(3)));
+    }
+    assert_eq!(stat.stack_count("poison"), 3);
+    assert_eq!(stat.value(), 4.0);
+    assert!(!stat.contains_modifier(key));
+
+    stat.apply_stacking(poison(StackingPolicy::Newest));
+    assert_eq!(stat.stack_count("poison"), 1);
+    assert_eq!(stat.value(), 8.0);
+    assert_eq!(stat.remove_stack("poison"), 1);
+    assert_eq!(stat.value(), 10.0);
+
+    // only the strongest applies, when it runs out the next one takes over
+    let slow = |amount: f32, seconds| {
+        StackingModifier::new(
+            "slow",
+            StatModifier::PercentMultiply(amount),
+            StackingPolicy::Strongest,
+        )
+        .with_duration(seconds)
+    };
+    stat.apply_stacking(slow(0.8, 10.0));
+    stat.apply_stacking(slow(0.5, 2.0));
+    assert_eq!(stat.value(), 5.0);
+    stat.tick(2.0);
+    assert_eq!(stat.stack_count("slow"), 1);
+    assert_eq!(stat.value(), 8.0);
+    stat.tick(8.0);
+    assert_eq!(stat.stack_count("slow"), 0);
+    assert_eq!(stat.value(), 10.0);
+}
+
+#[test]
+fn stacking_policy_strict_capacity() {
+    let mut stat: Stat<2> = Stat::new_strict(10.0);
+    let _handle = stat.add_modifier(StatModifier::Flat(1.0));
+    let rage = || StackingModifier::new("rage", StatModifier::Flat(5.0), StackingPolicy::Newest);
+    stat.apply_stacking(rage());
+    // replaces the old one, there is no need for more room
+    assert!(stat.try_apply_stacking(rage()).is_ok());
+    assert_eq!(stat.value(), 16.0);
+    assert_eq!(
+        stat.try_apply_stacking(StackingModifier::new(
+            "haste",
+            StatModifier::Flat(1.0),
+            StackingPolicy::Unique
+        )),
+        Err(StatError::CapacityExceeded { capacity: 2 })
+    );
+}
+
+#[test]
+fn stackable_modifier() {
+    let mut attack_speed: Stat<2> = Stat::new(100.0);
+    let frenzy = attack_speed.add_stackable_modifier(
+        StackableModifier::new(StatModifier::Flat(3.0))
+            .with_stacks(0)
+            .with_max_stacks(10)
+            .with_decay(1.0),
+    );
+    assert_eq!(attack_speed.value(), 100.0);
+    for _ in 0..4 {
+        attack_speed.add_stacks(frenzy.key(), 1);
+    }
+    assert_eq!(attack_speed.value(), 112.0);
+    assert_eq!(attack_speed.add_stacks(frenzy.key(), 20), Some(10));
+    assert_eq!(attack_speed.value(), 130.0);
+
+    let snapshot = attack_speed.snapshot();
+    attack_speed.tick(0.5);
+    assert_eq!(attack_speed.stacks(frenzy.key()), Some(10));
+    // a hit restarts the decay
+    attack_speed.add_stacks(frenzy.key(), 1);
+    attack_speed.tick(0.6);
+    assert_eq!(attack_speed.stacks(frenzy.key()), Some(10));
+    attack_speed.tick(2.5);
+    assert_eq!(attack_speed.stacks(frenzy.key()), Some(7));
+    assert_eq!(attack_speed.remove_stacks(frenzy.key(), 10), Some(0));
+    assert_eq!(attack_speed.value(), 100.0);
+    attack_speed.tick(5.0);
+    assert_eq!(attack_speed.stacks(frenzy.key()), Some(0));
+
+    attack_speed.restore(&snapshot);
+    assert_eq!(attack_speed.stacks(frenzy.key()), Some(10));
+    assert_eq!(attack_speed.value(), 130.0);
+
+    let key = frenzy.key();
+    drop(frenzy);
+    assert_eq!(attack_speed.value(), 100.0);
+    assert_eq!(attack_speed.stacks(key), None);
+    assert_eq!(attack_speed.add_stacks(key, 1), None);
+}
+
+#[test]
+fn aggregation_per_order_group() {
+    let mut stat: Stat<8> = Stat::new(10.0);
+    let _flats = [2.0, 5.0, -1.0].map(|v| stat.add_modifier(StatModifier::Flat(v)));
+    let _percents = [0.5, 0.25].map(|v| stat.add_modifier(StatModifier::PercentAdd(v)));
+    let _multipliers =
+        [2.0, 1.5, 0.5, 0.75].map(|v| stat.add_modifier(StatModifier::PercentMultiply(v)));
+
+    stat.set_aggregation(0, Aggregation::Max);
+    stat.set_aggregation(1, Aggregation::Sum);
+    stat.set_aggregation(2, Aggregation::StrongestBuffAndDebuff);
+    // 10 + 5, then +75%, then only x2 and x0.5
+    assert_eq!(stat.value(), 39.375);
+
+    stat.set_aggregation(0, Aggregation::Min);
+    stat.set_aggregation(1, Aggregation::Product);
+    stat.set_aggregation(2, Aggregation::Sequential);
+    assert_eq!(stat.aggregation(2), Aggregation::Sequential);
+    // 10 - 1, x1.5 x1.25, x2 x1.5 x0.5 x0.75
+    assert_eq!(stat.value(), 18.984375);
+
+    // rolled back with the modifiers
+    let snapshot = stat.snapshot();
+    stat.set_aggregation(1, Aggregation::Max);
+    assert_ne!(stat.value(), 18.984375);
+    stat.restore(&snapshot);
+    assert_eq!(stat.aggregation(1), Aggregation::Product);
+    assert_eq!(stat.value(), 18.984375);
+}
+
+#[test]
+fn caps_and_diminishing_returns() {
+    let mut damage: Stat<4> = Stat::new(100.0);
+    let _soft_cap = damage.add_modifier(StatModifier::SoftCap {
+        threshold: 100.0,
+        rate: 0.5,
+    });
+    let _bonus = damage.add_modifier(StatModifier::Flat(40.0));
+    assert_eq!(damage.value(), 120.0);
+    // placed after the cap, it still doubles the value
+    let _crit = damage.add_modifier_with_order(StatModifier::PercentMultiply(2.0), 10);
+    assert_eq!(damage.value(), 240.0);
+    let _hard_cap = damage.add_modifier_with_order(StatModifier::HardCap(200.0), 20);
+    assert_eq!(damage.value(), 200.0);
+
+    // armor is turned into damage reduction
+    let mut armor: Stat<2> = Stat::new(100.0);
+    let _reduction = armor.add_modifier(StatModifier::DiminishingReturns { max: 0.8, k: 100.0 });
+    assert_eq!(armor.value(), 0.4);
+    let _plate = armor.add_modifier(StatModifier::Flat(300.0));
+    assert_eq!(armor.value(), 0.64);
+}
+
+#[test]
+fn curve_interpolation() {
+    let keyframes = [(2.0, 20.0), (0.0, 0.0), (4.0, 20.0)];
+    let step = Curve::new(keyframes, Interpolation::Step).unwrap();
+    assert_eq!(step.keyframes()[0], (0.0, 0.0));
+    assert_eq!(step.sample(1.9), 0.0);
+    assert_eq!(step.sample(2.0), 20.0);
+    let linear = Curve::new(keyframes, Interpolation::Linear).unwrap();
+    assert_eq!(linear.sample(1.0), 10.0);
+    assert_eq!(linear.sample(-1.0), 0.0);
+    assert_eq!(linear.sample(3.0), 20.0);
+    let spline = Curve::new(keyframes, Interpolation::CatmullRom).unwrap();
+    assert_eq!(spline.sample(2.0), 20.0);
+    // the tangent at 2.0 still points up, so the spline overshoots between the two 20s
+    assert!(spline.sample(3.0) > 20.0);
+
+    assert_eq!(
+        Curve::new([], Interpolation::Linear),
+        Err(CurveError::Empty)
+    );
+    assert_eq!(
+        Curve::new([(1.0, 0.0), (1.0, 2.0)], Interpolation::Linear),
+        Err(CurveError::DuplicateInput(1.0))
+    );
+    assert_eq!(
+        Curve::new([(f32::NAN, 0.0)], Interpolation::Linear),
+        Err(CurveError::NotFinite)
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn curve_serde() {
+    let curve: Curve =
+        serde_json::from_str(r#"{ "keyframes": [[10.0, 1.0], [1.0, 0.0]] }"#).unwrap();
+    assert_eq!(curve.interpolation(), Interpolation::Linear);
+    assert_eq!(curve.sample(5.5), 0.5);
+    let json = serde_json::to_string(&curve).unwrap();
+    assert_eq!(serde_json::from_str::<Curve>(&json).unwrap(), curve);
+    let error = serde_json::from_str::<Curve>(r#"{ "keyframes": [] }"#).unwrap_err();
+    assert_eq!(error.to_string(), "curve has no keyframes");
+}
+
+#[test]
+fn level_growth() {
+    let exponential = Growth::Exponential {
+        base: 100.0,
+        rate: 2.0,
+    };
+    assert_eq!(exponential.value(0), 100.0);
+    assert_eq!(exponential.value(4), 800.0);
+    let table = Growth::Table(vec![10.0, 12.0, 15.0]);
+    assert_eq!(table.value(10), 15.0);
+    let curve = Curve::new([(1.0, 5.0), (11.0, 25.0)], Interpolation::Linear).unwrap();
+    assert_eq!(Growth::Curve(curve).value(6), 15.0);
+
+    let mut sheet: StatSheet<&str, 2> = StatSheet::new();
+    sheet.insert("health", Stat::with_growth(exponential, 1));
+    sheet.insert("strength", Stat::with_growth(table, 1));
+    sheet.insert("luck", Stat::new(7.0));
+    let _armor = sheet
+        .get_mut("health")
+        .unwrap()
+        .add_modifier(StatModifier::PercentAdd(0.5));
+    assert_eq!(sheet.value("health"), Some(150.0));
+
+    let snapshot = sheet.get("health").unwrap().snapshot();
+    sheet.set_level(3);
+    assert_eq!(sheet.value("health"), Some(600.0));
+    assert_eq!(sheet.value("strength"), Some(15.0));
+    assert_eq!(sheet.value("luck"), Some(7.0));
+    assert!(!sheet.get_mut("luck").unwrap().set_level(3));
+
+    let health = sheet.get_mut("health").unwrap();
+    health.restore(&snapshot);
+    assert_eq!(health.level(), Some(1));
+    assert_eq!(health.value(), 150.0);
+    health.set_level(2);
+    assert_eq!(health.value(), 300.0);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn level_growth_serde() {
+    let stat: Stat<2> = Stat::with_growth(
+        Growth::Linear {
+            base: 10.0,
+            per_level: 2.0,
+        },
+        5,
+    );
+    let json = serde_json::to_string(&stat).unwrap();
+    let mut deserialized: Stat<2> = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized.value(), 18.0);
+    deserialized.set_level(6);
+    assert_eq!(deserialized.value(), 20.0);
+}
+
+#[test]
+fn base_and_bonus_tiers() {
+    let mut strength: Stat<8> = Stat::new(40.0);
+    let _talent = strength.insert_base_modifier(StatModifier::Flat(10.0));
+    let _gear = strength.add_modifier(StatModifier::Flat(20.0));
+    let _buff = strength.add_modifier(StatModifier::PercentAdd(0.5));
+    // the bonus tier scales the whole value
+    assert_eq!(strength.value(), 105.0);
+    assert_eq!(strength.base_total(), 50.0);
+    assert_eq!(strength.bonus(), 55.0);
+
+    let potion = strength.apply_stacking(StackingModifier::new(
+        "potion",
+        StatModifier::PercentAdd(0.5),
+        StackingPolicy::Refresh,
+    ));
+    let Stacked::Added(potion) = potion else {
+        panic!("first application is added");
+    };
+    assert!(strength.set_modifier_tier(potion, ModifierTier::Base));
+    assert_eq!(strength.base_total(), 75.0);
+    assert_eq!(strength.value(), 142.5);
+
+    let mut weakened = strength.clone();
+    let _curse = weakened.add_modifier(StatModifier::Flat(-65.0));
+    assert_eq!(weakened.bonus(), -30.0);
+    let dropped = strength.add_modifier(StatModifier::Flat(1.0)).key();
+    assert!(!strength.set_modifier_tier(dropped, ModifierTier::Base));
+}
+
+#[test]
+fn increased_and_more_stages() {
+    let mut damage: Stat<8> = Stat::new(10.0);
+    // added in the wrong order on purpose, the default orders put them in stages
+    let _more = damage.add_modifier(StatModifier::More(0.5));
+    let _increased = damage.add_modifier(StatModifier::Increased(0.5));
+    let _added = damage.add_modifier(StatModifier::Flat(5.0));
+    let _more_again = damage.add_modifier(StatModifier::More(1.0));
+    let _increased_again = damage.add_modifier(StatModifier::Increased(0.25));
+    let _added_again = damage.add_modifier(StatModifier::Flat(5.0));
+    // (10 + 5 + 5) * (1 + 0.5 + 0.25) * 1.5 * 2
+    assert_eq!(damage.value(), 105.0);
+
+    // percent add still compounds
+    let _percent = damage.add_modifier(StatModifier::PercentAdd(1.0));
+    assert_eq!(damage.value(), 210.0);
+
+    assert_eq!(StatModifier::More(0.5).stacked(2), StatModifier::More(1.25));
+    assert_eq!(
+        StatModifier::Increased(0.25).stacked(2),
+        StatModifier::Increased(0.5)
+    );
+}
+
 #[cfg(feature = "sync")]
 #[test]
 pub fn multithreaded_environment() {
@@ -195,3 +1500,19 @@ pub fn multithreaded_environment() {
     handle_2.join().unwrap();
     assert!(stat.lock().unwrap().value() == 0.0f32);
 }
+
+#[cfg(feature = "sync")]
+#[test]
+pub fn multithreaded_reads() {
+    let mut stat = Stat::<2>::new(10.0f32);
+    let _modifier_handle = stat.add_modifier(StatModifier::Flat(1.0));
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for _ in 0..100 {
+                    assert_eq!(stat.value(), 11.0f32);
+                }
+            });
+        }
+    });
+}